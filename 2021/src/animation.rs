@@ -0,0 +1,38 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+// a frame as it is shown in the terminal and as it is written to an image file
+pub struct Frame {
+    pub screen: String,
+    pub file_name: String,
+    pub image: String,
+}
+
+// redraws the terminal for every frame and optionally writes each frame into `frames_dir`
+pub fn animate<T>(
+    frames: &[T],
+    delay: Duration,
+    frames_dir: Option<&Path>,
+    draw: impl Fn(usize, &T) -> Frame,
+) -> io::Result<()> {
+    if let Some(dir) = frames_dir {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut stdout = io::stdout();
+    for (i, frame) in frames.iter().enumerate() {
+        let frame = draw(i, frame);
+        write!(stdout, "\x1b[2J\x1b[H{}", frame.screen)?;
+        stdout.flush()?;
+
+        if let Some(dir) = frames_dir {
+            fs::write(dir.join(frame.file_name), frame.image)?;
+        }
+
+        thread::sleep(delay);
+    }
+    Ok(())
+}
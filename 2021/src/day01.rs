@@ -33,7 +33,7 @@ fn sliding_window(depths: &[i32]) -> Vec<i32> {
 }
 
 fn puzzle1(depths: &[i32]) -> i32 {
-    count_increases(&depths)
+    count_increases(depths)
 }
 
 fn puzzle2(depths: &[i32]) -> i32 {
@@ -47,9 +47,7 @@ fn puzzle1(lines: &[String]) -> u32 {
         bit_count < (lines.len() as u32 / 2)
     });
 
-    let power_consumption = gamma_rate * epsilon_rate;
-
-    power_consumption
+    gamma_rate * epsilon_rate
 }
 
 fn to_uints(lines: &[String]) -> Vec<u32> {
@@ -78,12 +76,12 @@ fn partition_least_most_common(report: &[u32], bit_index: usize) -> (Vec<u32>, V
 }
 
 fn filter_most_common(report: &[u32], bit_index: usize) -> Vec<u32> {
-    let (_, most_common) = partition_least_most_common(&report, bit_index);
+    let (_, most_common) = partition_least_most_common(report, bit_index);
     most_common
 }
 
 fn filter_least_common(report: &[u32], bit_index: usize) -> Vec<u32> {
-    let (least_common, _) = partition_least_most_common(&report, bit_index);
+    let (least_common, _) = partition_least_most_common(report, bit_index);
     least_common
 }
 
@@ -110,9 +108,7 @@ fn puzzle2(diagnostic_report: &[u32], bit_width: usize) -> u32 {
     let oxygen_generator_rating = most_common.first().unwrap();
     let co2_scrubber_rating = least_common.first().unwrap();
 
-    let life_support_rating = oxygen_generator_rating * co2_scrubber_rating;
-
-    life_support_rating
+    oxygen_generator_rating * co2_scrubber_rating
 }
 
 pub fn day03() {
@@ -74,8 +74,8 @@ fn check_board(board: &BingoBoard) -> Option<i32> {
     }
 }
 
-fn puzzle1(drawn_numbers: &[i32], boards: &Vec<BingoBoard>) -> i32 {
-    let mut boards = boards.clone();
+fn puzzle1(drawn_numbers: &[i32], boards: &[BingoBoard]) -> i32 {
+    let mut boards = boards.to_vec();
     for number in drawn_numbers {
         for board in &mut boards {
             draw_number(*number, board);
@@ -88,8 +88,8 @@ fn puzzle1(drawn_numbers: &[i32], boards: &Vec<BingoBoard>) -> i32 {
     panic!("could not find a winning board!");
 }
 
-fn puzzle2(drawn_numbers: &[i32], boards: &Vec<BingoBoard>) -> i32 {
-    let mut boards = boards.clone();
+fn puzzle2(drawn_numbers: &[i32], boards: &[BingoBoard]) -> i32 {
+    let mut boards = boards.to_vec();
     let mut last_winning_score = None;
 
     for number in drawn_numbers {
@@ -98,7 +98,7 @@ fn puzzle2(drawn_numbers: &[i32], boards: &Vec<BingoBoard>) -> i32 {
         }
         // iterate a second time so that numbers are marked for all boards
         boards.retain(|board| {
-            if let Some(unmarked_sum) = check_board(&board) {
+            if let Some(unmarked_sum) = check_board(board) {
                 last_winning_score = Some(unmarked_sum * number);
                 false
             } else {
@@ -1,18 +1,41 @@
-#[derive(Debug, PartialEq, Clone, Copy)]
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 struct Point {
     x: i32,
     y: i32,
 }
 
+#[derive(Clone, Copy)]
 struct Line {
     start: Point,
     end: Point,
 }
 
-fn parse_lines(input: &str) -> Vec<Line> {
+// lines that are neither horizontal, vertical nor at 45 degrees
+#[derive(Debug, PartialEq)]
+struct SlantedLine {
+    line_number: usize,
+    start: Point,
+    end: Point,
+}
+
+impl fmt::Display for SlantedLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {},{} -> {},{} is neither horizontal, vertical nor diagonal",
+            self.line_number, self.start.x, self.start.y, self.end.x, self.end.y
+        )
+    }
+}
+
+fn parse_lines(input: &str) -> Result<Vec<Line>, SlantedLine> {
     input
         .lines()
-        .map(|line| {
+        .enumerate()
+        .map(|(i, line)| {
             let mut points = line.split(" -> ").map(|point| {
                 let mut nums = point.split(',').map(|num| num.parse().unwrap());
 
@@ -22,90 +45,332 @@ fn parse_lines(input: &str) -> Vec<Line> {
                 }
             });
 
-            Line {
+            let line = Line {
                 start: points.next().unwrap(),
                 end: points.next().unwrap(),
+            };
+            if line.is_straight() || line.is_diagonal() {
+                Ok(line)
+            } else {
+                Err(SlantedLine {
+                    line_number: i + 1,
+                    start: line.start,
+                    end: line.end,
+                })
             }
         })
         .collect()
 }
 
-type Grid = Vec<Vec<i32>>;
+impl Line {
+    fn is_straight(&self) -> bool {
+        self.start.x == self.end.x || self.start.y == self.end.y
+    }
+
+    fn is_diagonal(&self) -> bool {
+        (self.end.x as i64 - self.start.x as i64).abs()
+            == (self.end.y as i64 - self.start.y as i64).abs()
+    }
+}
 
-fn make_grid(width: usize, height: usize) -> Grid {
-    vec![vec![0; width]; height]
+// how many lines cover a point, only counting horizontal and vertical ones and counting all
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Overlaps {
+    straight: u32,
+    all: u32,
 }
 
-fn draw_line(line: &Line, grid: &mut Grid) {
+// only the points that are covered at all are stored, so coordinates can be anywhere
+type VentMap = HashMap<Point, Overlaps>;
+
+fn draw_line(line: &Line, vents: &mut VentMap) {
+    // comparing instead of subtracting can't overflow at the edges of the i32 range
     let move_to = |p: &mut Point, end: &Point| {
-        p.x += (end.x - p.x).signum();
-        p.y += (end.y - p.y).signum();
+        p.x += end.x.cmp(&p.x) as i32;
+        p.y += end.y.cmp(&p.y) as i32;
+    };
+    let straight = line.is_straight();
+    let mut plot = |point: Point| {
+        let overlaps = vents.entry(point).or_default();
+        overlaps.all += 1;
+        overlaps.straight += u32::from(straight);
     };
 
     let mut point = line.start;
 
-    grid[point.y as usize][point.x as usize] += 1;
+    plot(point);
     while point != line.end {
         move_to(&mut point, &line.end);
-        grid[point.y as usize][point.x as usize] += 1;
+        plot(point);
     }
 }
 
-fn puzzle1(width: usize, height: usize, lines: &[Line]) -> usize {
-    let mut grid = make_grid(width, height);
+fn map_vents(lines: &[Line]) -> VentMap {
+    let mut vents = VentMap::new();
+    for line in lines {
+        draw_line(line, &mut vents);
+    }
+    vents
+}
 
-    for line in lines
-        .iter()
-        .filter(|line| line.start.x == line.end.x || line.start.y == line.end.y)
-    {
-        draw_line(line, &mut grid);
+// points where at least two horizontal or vertical lines overlap
+fn puzzle1(vents: &VentMap) -> usize {
+    vents
+        .values()
+        .filter(|overlaps| overlaps.straight >= 2)
+        .count()
+}
+
+fn puzzle2(vents: &VentMap) -> usize {
+    vents.values().filter(|overlaps| overlaps.all >= 2).count()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Direction {
+    Horizontal,
+    Vertical,
+    // going up to the right
+    Diagonal,
+    AntiDiagonal,
+}
+
+impl Direction {
+    // every line in this direction is a * x + b * y = key for some key
+    fn coefficients(self) -> (i64, i64) {
+        match self {
+            Direction::Horizontal => (0, 1),
+            Direction::Vertical => (1, 0),
+            Direction::Diagonal => (-1, 1),
+            Direction::AntiDiagonal => (1, 1),
+        }
     }
 
-    grid.iter().fold(0, |sum, row| {
-        sum + row
-            .iter()
-            .filter(|&num_overlaps| *num_overlaps >= 2)
-            .count()
+    fn key(self, (x, y): (i64, i64)) -> i64 {
+        let (a, b) = self.coefficients();
+        a * x + b * y
+    }
+
+    // where a point is along the line
+    fn position(self, (x, y): (i64, i64)) -> i64 {
+        match self {
+            Direction::Vertical => y,
+            _ => x,
+        }
+    }
+}
+
+// a line as the infinite line it lies on and the range of positions it covers there
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Span {
+    direction: Direction,
+    key: i64,
+    from: i64,
+    to: i64,
+}
+
+impl Span {
+    fn from_line(line: &Line) -> Span {
+        assert!(
+            line.is_straight() || line.is_diagonal(),
+            "lines have to be horizontal, vertical or diagonal"
+        );
+        let start = (line.start.x as i64, line.start.y as i64);
+        let end = (line.end.x as i64, line.end.y as i64);
+        let direction = if start.1 == end.1 {
+            Direction::Horizontal
+        } else if start.0 == end.0 {
+            Direction::Vertical
+        } else if end.0 - start.0 == end.1 - start.1 {
+            Direction::Diagonal
+        } else {
+            Direction::AntiDiagonal
+        };
+
+        let (a, b) = (direction.position(start), direction.position(end));
+        Span {
+            direction,
+            key: direction.key(start),
+            from: a.min(b),
+            to: a.max(b),
+        }
+    }
+
+    fn contains(&self, point: (i64, i64)) -> bool {
+        self.direction.key(point) == self.key
+            && (self.from..=self.to).contains(&self.direction.position(point))
+    }
+
+    fn len(&self) -> usize {
+        (self.to - self.from + 1) as usize
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Intersection {
+    Nothing,
+    Point((i64, i64)),
+    Overlap(Span),
+}
+
+fn intersect(a: &Span, b: &Span) -> Intersection {
+    if a.direction == b.direction {
+        let (from, to) = (a.from.max(b.from), a.to.min(b.to));
+        return if a.key == b.key && from <= to {
+            Intersection::Overlap(Span { from, to, ..*a })
+        } else {
+            Intersection::Nothing
+        };
+    }
+
+    // cramer's rule on both line equations
+    let (a1, b1) = a.direction.coefficients();
+    let (a2, b2) = b.direction.coefficients();
+    let det = a1 * b2 - a2 * b1;
+    let x = a.key * b2 - b.key * b1;
+    let y = a1 * b.key - a2 * a.key;
+    if x % det != 0 || y % det != 0 {
+        // the diagonals cross between two grid points
+        return Intersection::Nothing;
+    }
+
+    let point = (x / det, y / det);
+    if a.contains(point) && b.contains(point) {
+        Intersection::Point(point)
+    } else {
+        Intersection::Nothing
+    }
+}
+
+// disjoint, sorted ranges of overlapping lines, per infinite line
+type Runs = HashMap<(Direction, i64), Vec<(i64, i64)>>;
+
+// how many runs on different lines go through a point
+fn runs_through(runs: &Runs, point: (i64, i64)) -> usize {
+    [
+        Direction::Horizontal,
+        Direction::Vertical,
+        Direction::Diagonal,
+        Direction::AntiDiagonal,
+    ]
+    .into_iter()
+    .filter(|&direction| {
+        runs.get(&(direction, direction.key(point)))
+            .is_some_and(|ranges| {
+                let position = direction.position(point);
+                let i = ranges.partition_point(|&(_, to)| to < position);
+                ranges.get(i).is_some_and(|&(from, _)| from <= position)
+            })
     })
+    .count()
 }
 
-fn puzzle2(width: usize, height: usize, lines: &[Line]) -> usize {
-    let mut grid = make_grid(width, height);
-    for line in lines {
-        draw_line(line, &mut grid);
+// counts the points where at least two lines overlap without visiting every point of every line.
+// collinear lines overlap in runs, which are merged per infinite line and counted by length,
+// everything else crosses in single points. O(lines^2), no matter how long the lines are.
+fn count_overlaps(lines: &[Line]) -> usize {
+    let spans: Vec<Span> = lines.iter().map(Span::from_line).collect();
+
+    let mut crossings = HashSet::new();
+    let mut runs = Runs::new();
+    for (i, a) in spans.iter().enumerate() {
+        for b in &spans[i + 1..] {
+            match intersect(a, b) {
+                Intersection::Nothing => {}
+                Intersection::Point(point) => {
+                    crossings.insert(point);
+                }
+                Intersection::Overlap(span) => runs
+                    .entry((span.direction, span.key))
+                    .or_default()
+                    .push((span.from, span.to)),
+            }
+        }
+    }
+
+    for ranges in runs.values_mut() {
+        ranges.sort_unstable();
+        let mut merged: Vec<(i64, i64)> = Vec::with_capacity(ranges.len());
+        for &(from, to) in ranges.iter() {
+            match merged.last_mut() {
+                Some(last) if from <= last.1 + 1 => last.1 = last.1.max(to),
+                _ => merged.push((from, to)),
+            }
+        }
+        *ranges = merged;
     }
 
-    grid.iter().fold(0, |sum, row| {
-        sum + row
+    let run_spans: Vec<Span> = runs
+        .iter()
+        .flat_map(|(&(direction, key), ranges)| {
+            ranges.iter().map(move |&(from, to)| Span {
+                direction,
+                key,
+                from,
+                to,
+            })
+        })
+        .collect();
+    let mut count: usize = run_spans.iter().map(Span::len).sum();
+
+    // where runs on different lines cross, the point has been counted once for each of them
+    let mut run_crossings = HashSet::new();
+    for (i, a) in run_spans.iter().enumerate() {
+        for b in &run_spans[i + 1..] {
+            if let Intersection::Point(point) = intersect(a, b) {
+                run_crossings.insert(point);
+            }
+        }
+    }
+    for &point in &run_crossings {
+        count -= runs_through(&runs, point) - 1;
+    }
+
+    count
+        + crossings
             .iter()
-            .filter(|&num_overlaps| *num_overlaps >= 2)
+            .filter(|&&point| runs_through(&runs, point) == 0)
             .count()
-    })
 }
 
 pub fn day05() {
     println!("\nDay 5:");
     let input =
-        parse_lines(&std::fs::read_to_string("inputs/day05").expect("could not read input"));
+        parse_lines(&std::fs::read_to_string("inputs/day05").expect("could not read input"))
+            .unwrap_or_else(|e| panic!("{}", e));
+
+    // both puzzles come out of the same map
+    let vents = map_vents(&input);
 
     println!("Puzzle 1:");
-    let p1_result = puzzle1(1000, 1000, &input);
+    let p1_result = puzzle1(&vents);
     println!(
         "The number of points where at least two lines overlap is {}",
         p1_result
     );
 
     println!("Puzzle 2:");
-    let p2_result = puzzle2(1000, 1000, &input);
+    let p2_result = puzzle2(&vents);
     println!(
         "The number of points where at least two lines overlap (including diagonals) is {}",
         p2_result
     );
+
+    let straight_lines: Vec<Line> = input
+        .iter()
+        .filter(|line| line.is_straight())
+        .copied()
+        .collect();
+    println!(
+        "Intersecting the lines instead of drawing them gives {} and {} points",
+        count_overlaps(&straight_lines),
+        count_overlaps(&input)
+    );
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::rng::Lcg;
 
     const EXAMPLE_INPUT: &str = r"0,9 -> 5,9
 8,0 -> 0,8
@@ -121,15 +386,157 @@ mod tests {
 
     #[test]
     fn puzzle1_example() {
-        let input = parse_lines(&EXAMPLE_INPUT);
-        let two_or_more_overlaps = puzzle1(10, 10, &input);
+        let input = parse_lines(EXAMPLE_INPUT).unwrap();
+        let two_or_more_overlaps = puzzle1(&map_vents(&input));
         assert_eq!(two_or_more_overlaps, 5);
     }
 
     #[test]
     fn puzzle2_example() {
-        let input = parse_lines(&EXAMPLE_INPUT);
-        let two_or_more_overlaps = puzzle2(10, 10, &input);
+        let input = parse_lines(EXAMPLE_INPUT).unwrap();
+        let two_or_more_overlaps = puzzle2(&map_vents(&input));
         assert_eq!(two_or_more_overlaps, 12);
     }
+
+    #[test]
+    fn arbitrary_coordinates() {
+        // the example, moved far away from the origin in both directions
+        for (dx, dy) in [
+            (-5000, -20),
+            (1_000_000, -3),
+            (-2_000_000_000, 2_000_000_000),
+        ] {
+            let input: Vec<Line> = parse_lines(EXAMPLE_INPUT)
+                .unwrap()
+                .iter()
+                .map(|line| {
+                    let shift = |p: Point| Point {
+                        x: p.x + dx,
+                        y: p.y + dy,
+                    };
+                    Line {
+                        start: shift(line.start),
+                        end: shift(line.end),
+                    }
+                })
+                .collect();
+            let vents = map_vents(&input);
+            assert_eq!((puzzle1(&vents), puzzle2(&vents)), (5, 12));
+        }
+
+        let input = parse_lines(
+            "2147483645,-2147483648 -> 2147483647,-2147483646
+2147483647,-2147483648 -> 2147483645,-2147483646
+2147483646,-2147483648 -> 2147483646,-2147483646
+",
+        )
+        .unwrap();
+        let vents = map_vents(&input);
+        assert_eq!(vents.len(), 7);
+        assert_eq!(
+            vents[&Point {
+                x: 2147483646,
+                y: -2147483647
+            }],
+            Overlaps {
+                straight: 1,
+                all: 3
+            }
+        );
+        assert_eq!((puzzle1(&vents), puzzle2(&vents)), (0, 1));
+    }
+
+    // both parts by drawing and by intersecting the lines
+    fn cross_check(lines: &[Line]) {
+        let vents = map_vents(lines);
+        let straight_lines: Vec<Line> = lines
+            .iter()
+            .filter(|line| line.is_straight())
+            .copied()
+            .collect();
+        assert_eq!(count_overlaps(&straight_lines), puzzle1(&vents));
+        assert_eq!(count_overlaps(lines), puzzle2(&vents));
+    }
+
+    #[test]
+    fn intersection_test() {
+        cross_check(&parse_lines(EXAMPLE_INPUT).unwrap());
+        cross_check(
+            &parse_lines(&std::fs::read_to_string("inputs/day05").expect("could not read input"))
+                .unwrap(),
+        );
+        assert_eq!(count_overlaps(&parse_lines(EXAMPLE_INPUT).unwrap()), 12);
+        assert_eq!(count_overlaps(&[]), 0);
+
+        // lots of short lines in a small area, so most of them overlap or cross somehow
+        let mut rng = Lcg::new(5);
+        let mut next = |range: i32| rng.below(range as u64) as i32;
+        for _ in 0..20 {
+            let lines: Vec<Line> = (0..60)
+                .map(|_| {
+                    let start = Point {
+                        x: next(20) - 5,
+                        y: next(20) - 5,
+                    };
+                    let length = next(12);
+                    let (dx, dy) =
+                        [(1, 0), (0, 1), (1, 1), (1, -1), (-1, 0), (-1, -1)][next(6) as usize];
+                    Line {
+                        start,
+                        end: Point {
+                            x: start.x + dx * length,
+                            y: start.y + dy * length,
+                        },
+                    }
+                })
+                .collect();
+            cross_check(&lines);
+        }
+    }
+
+    #[test]
+    fn slanted_lines() {
+        assert_eq!(
+            parse_lines("0,0 -> 2,2\n0,0 -> 3,1\n").map(|lines| lines.len()),
+            Err(SlantedLine {
+                line_number: 2,
+                start: Point { x: 0, y: 0 },
+                end: Point { x: 3, y: 1 },
+            })
+        );
+        assert!(parse_lines("5,5 -> 5,5\n-3,4 -> 0,1\n").is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "lines have to be horizontal, vertical or diagonal")]
+    fn slanted_span() {
+        Span::from_line(&Line {
+            start: Point { x: 0, y: 0 },
+            end: Point { x: 1, y: 2 },
+        });
+    }
+
+    #[test]
+    fn long_lines() {
+        let lines = parse_lines(
+            "0,0 -> 1000000000,1000000000
+0,1000000000 -> 1000000000,0
+-1000000000,500000000 -> 1000000000,500000000
+500,500 -> 2000000000,2000000000
+7,0 -> 7,10
+",
+        )
+        .unwrap();
+        // the diagonals overlap from 500 to a billion and everything else crosses on that run,
+        // except for the vertical line that only hits the first diagonal
+        assert_eq!(count_overlaps(&lines), 1_000_000_000 - 500 + 1 + 1);
+
+        assert_eq!(
+            intersect(
+                &Span::from_line(&lines[0]),
+                &Span::from_line(&parse_lines("0,1 -> 1,0").unwrap()[0])
+            ),
+            Intersection::Nothing
+        );
+    }
 }
@@ -1,3 +1,9 @@
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::{fmt, fs, io};
+
+use crate::options;
+
 fn read_input(input: &str) -> Vec<usize> {
     input
         .trim_end()
@@ -6,12 +12,36 @@ fn read_input(input: &str) -> Vec<usize> {
         .collect()
 }
 
-fn age_fishies(fishies: &mut [usize]) -> usize {
+// a fish with a timer of 0 starts over at `reset_timer` and spawns a new fish at `newborn_timer`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Lifecycle {
+    reset_timer: usize,
+    newborn_timer: usize,
+}
+
+const LANTERNFISH: Lifecycle = Lifecycle {
+    reset_timer: 6,
+    newborn_timer: 8,
+};
+
+// species don't interact, so each of them can be simulated on its own
+struct Species {
+    lifecycle: Lifecycle,
+    timers: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Model {
+    Naive,
+    Pipeline,
+}
+
+fn age_fishies(fishies: &mut [usize], lifecycle: &Lifecycle) -> usize {
     let mut new_fishies = 0;
 
     for fishie in fishies {
         if *fishie == 0 {
-            *fishie = 6;
+            *fishie = lifecycle.reset_timer;
             new_fishies += 1;
         } else {
             *fishie -= 1;
@@ -21,32 +51,359 @@ fn age_fishies(fishies: &mut [usize]) -> usize {
     new_fishies
 }
 
-// naive approach
-fn puzzle1(input: &[usize], days: usize) -> usize {
-    let mut fishies = input.to_vec();
+// naive approach, runs out of memory long before the count could overflow
+fn simulate_naive(timers: &[usize], lifecycle: &Lifecycle, days: usize) -> Option<usize> {
+    let mut fishies = timers.to_vec();
 
     for _ in 0..days {
-        let new_fishies = age_fishies(&mut fishies);
-        fishies.resize_with(fishies.len() + new_fishies, || 8);
+        let new_fishies = age_fishies(&mut fishies, lifecycle);
+        fishies.resize_with(fishies.len() + new_fishies, || lifecycle.newborn_timer);
     }
 
-    fishies.len()
+    Some(fishies.len())
 }
 
-// thats a rotate!
-fn puzzle2(input: &[usize], days: usize) -> usize {
-    // array of fish counts where the index represents the days until the fish reproduces
-    let mut fish_pipeline = [0; 9];
-    for remaining_days in input {
+// fish counts where the index represents the days until the fish reproduces
+fn fill_pipeline(timers: &[usize], lifecycle: &Lifecycle) -> Vec<usize> {
+    let longest = timers
+        .iter()
+        .chain([&lifecycle.reset_timer, &lifecycle.newborn_timer])
+        .max()
+        .unwrap();
+    let mut fish_pipeline = vec![0; longest + 1];
+    for remaining_days in timers {
         fish_pipeline[*remaining_days] += 1;
     }
+    fish_pipeline
+}
+
+// thats a rotate! None if a count overflows
+fn advance_pipeline(fish_pipeline: &mut [usize], lifecycle: &Lifecycle) -> Option<()> {
+    let spawning = fish_pipeline[0];
+    fish_pipeline.rotate_left(1);
+    *fish_pipeline.last_mut().unwrap() = 0;
+    // fish with a value of 0 start over
+    fish_pipeline[lifecycle.reset_timer] =
+        fish_pipeline[lifecycle.reset_timer].checked_add(spawning)?;
+    // and produce a new fish
+    fish_pipeline[lifecycle.newborn_timer] =
+        fish_pipeline[lifecycle.newborn_timer].checked_add(spawning)?;
+    Some(())
+}
+
+fn population(fish_pipeline: &[usize]) -> Option<usize> {
+    fish_pipeline
+        .iter()
+        .try_fold(0usize, |sum, &count| sum.checked_add(count))
+}
 
+fn simulate_pipeline(timers: &[usize], lifecycle: &Lifecycle, days: usize) -> Option<usize> {
+    let mut fish_pipeline = fill_pipeline(timers, lifecycle);
     for _ in 0..days {
-        fish_pipeline.rotate_left(1); // fish with a value of 0 produce new fish with a value of 8
-        fish_pipeline[6] += fish_pipeline[8]; // but also start over with a value of 6 themselves
+        advance_pipeline(&mut fish_pipeline, lifecycle)?;
+    }
+    population(&fish_pipeline)
+}
+
+#[derive(Debug, PartialEq)]
+struct DayStats {
+    day: usize,
+    population: usize,
+    // fish per timer value, the pipeline on that day
+    timers: Vec<usize>,
+}
+
+// None if the population no longer fits before the end of the range
+fn timeline(
+    timers: &[usize],
+    lifecycle: &Lifecycle,
+    days: RangeInclusive<usize>,
+) -> Option<Vec<DayStats>> {
+    let mut fish_pipeline = fill_pipeline(timers, lifecycle);
+    let mut stats = Vec::new();
+    for day in 0..=*days.end() {
+        if day > 0 {
+            advance_pipeline(&mut fish_pipeline, lifecycle)?;
+        }
+        if days.contains(&day) {
+            stats.push(DayStats {
+                day,
+                population: population(&fish_pipeline)?,
+                timers: fish_pipeline.clone(),
+            });
+        }
+    }
+    Some(stats)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Csv,
+    Json,
+}
+
+fn export_timeline(timeline: &[DayStats], format: Format) -> String {
+    let join = |timers: &[usize]| {
+        timers
+            .iter()
+            .map(|count| count.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+
+    match format {
+        Format::Csv => {
+            let timer_count = timeline.first().map_or(0, |stats| stats.timers.len());
+            let mut csv = String::from("day,population");
+            for timer in 0..timer_count {
+                csv += &format!(",timer_{}", timer);
+            }
+            csv += "\n";
+            for stats in timeline {
+                csv += &format!(
+                    "{},{},{}\n",
+                    stats.day,
+                    stats.population,
+                    join(&stats.timers)
+                );
+            }
+            csv
+        }
+        Format::Json => {
+            let days: Vec<String> = timeline
+                .iter()
+                .map(|stats| {
+                    format!(
+                        "  {{\"day\": {}, \"population\": {}, \"timers\": [{}]}}",
+                        stats.day,
+                        stats.population,
+                        join(&stats.timers)
+                    )
+                })
+                .collect();
+            format!("[\n{}\n]\n", days.join(",\n"))
+        }
+    }
+}
+
+fn school_size(school: &[Species], days: usize, model: Model) -> Option<usize> {
+    let simulate = match model {
+        Model::Naive => simulate_naive,
+        Model::Pipeline => simulate_pipeline,
+    };
+    school.iter().try_fold(0usize, |sum, species| {
+        sum.checked_add(simulate(&species.timers, &species.lifecycle, days)?)
+    })
+}
+
+fn lanternfish(input: &[usize]) -> [Species; 1] {
+    [Species {
+        lifecycle: LANTERNFISH,
+        timers: input.to_vec(),
+    }]
+}
+
+fn puzzle1(input: &[usize], days: usize) -> Option<usize> {
+    school_size(&lanternfish(input), days, Model::Naive)
+}
+
+fn puzzle2(input: &[usize], days: usize) -> Option<usize> {
+    school_size(&lanternfish(input), days, Model::Pipeline)
+}
+
+fn write_timeline(dir: &Path, timeline: &[DayStats]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(
+        dir.join("timeline.csv"),
+        export_timeline(timeline, Format::Csv),
+    )?;
+    fs::write(
+        dir.join("timeline.json"),
+        export_timeline(timeline, Format::Json),
+    )?;
+    Ok(())
+}
+
+// the arithmetic the transition matrix is exponentiated with
+trait Count: Clone {
+    fn from_u64(n: u64) -> Self;
+    fn add(&self, other: &Self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+}
+
+// native counts where `None` means the count overflowed
+impl Count for Option<u64> {
+    fn from_u64(n: u64) -> Self {
+        Some(n)
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        self.zip(*other).and_then(|(a, b)| a.checked_add(b))
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        self.zip(*other).and_then(|(a, b)| a.checked_mul(b))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Modular<const M: u64>(u64);
+
+impl<const M: u64> Count for Modular<M> {
+    fn from_u64(n: u64) -> Self {
+        Modular(n % M)
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        Modular(((self.0 as u128 + other.0 as u128) % M as u128) as u64)
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        Modular((self.0 as u128 * other.0 as u128 % M as u128) as u64)
+    }
+}
+
+impl<const M: u64> fmt::Display for Modular<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (mod {})", self.0, M)
+    }
+}
+
+// little endian base 2^32 digits, without trailing zeros
+#[derive(Debug, Clone, PartialEq)]
+struct BigUint(Vec<u32>);
+
+impl BigUint {
+    fn trimmed(mut digits: Vec<u32>) -> Self {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigUint(digits)
+    }
+
+    // divides in place and returns the remainder
+    fn div_rem(&mut self, divisor: u32) -> u32 {
+        let mut remainder = 0u64;
+        for digit in self.0.iter_mut().rev() {
+            let current = remainder << 32 | *digit as u64;
+            *digit = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        *self = BigUint::trimmed(std::mem::take(&mut self.0));
+        remainder as u32
+    }
+}
+
+impl Count for BigUint {
+    fn from_u64(n: u64) -> Self {
+        BigUint::trimmed(vec![n as u32, (n >> 32) as u32])
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        let mut sum = Vec::with_capacity(self.0.len().max(other.0.len()) + 1);
+        let mut carry = 0u64;
+        for i in 0..self.0.len().max(other.0.len()) {
+            let a = *self.0.get(i).unwrap_or(&0) as u64;
+            let b = *other.0.get(i).unwrap_or(&0) as u64;
+            let digit = a + b + carry;
+            sum.push(digit as u32);
+            carry = digit >> 32;
+        }
+        sum.push(carry as u32);
+        BigUint::trimmed(sum)
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        let mut product = vec![0u32; self.0.len() + other.0.len()];
+        for (i, &a) in self.0.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.0.iter().enumerate() {
+                let digit = product[i + j] as u64 + a as u64 * b as u64 + carry;
+                product[i + j] = digit as u32;
+                carry = digit >> 32;
+            }
+            product[i + other.0.len()] = carry as u32;
+        }
+        BigUint::trimmed(product)
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // peel off nine decimal digits at a time
+        let mut rest = self.clone();
+        let mut chunks = vec![rest.div_rem(1_000_000_000)];
+        while !rest.0.is_empty() {
+            chunks.push(rest.div_rem(1_000_000_000));
+        }
+
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
     }
+}
 
-    fish_pipeline.iter().sum()
+type Matrix<T> = Vec<Vec<T>>;
+
+fn mul_matrix<T: Count>(a: &Matrix<T>, b: &Matrix<T>) -> Matrix<T> {
+    a.iter()
+        .map(|a_row| {
+            (0..b[0].len())
+                .map(|j| {
+                    a_row
+                        .iter()
+                        .zip(b)
+                        .fold(T::from_u64(0), |sum, (a_ik, b_row)| {
+                            sum.add(&a_ik.mul(&b_row[j]))
+                        })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// transitions[to][from] is how many fish with timer `to` a single fish with timer `from` turns
+// into after one day
+fn transition_matrix<T: Count>(lifecycle: &Lifecycle, size: usize) -> Matrix<T> {
+    let mut transitions = vec![vec![0; size]; size];
+    for timer in 1..size {
+        transitions[timer - 1][timer] += 1;
+    }
+    transitions[lifecycle.reset_timer][0] += 1;
+    transitions[lifecycle.newborn_timer][0] += 1;
+
+    transitions
+        .into_iter()
+        .map(|row| row.into_iter().map(T::from_u64).collect())
+        .collect()
+}
+
+// every day is the same linear map on the pipeline, so squaring it skips ahead in O(log days).
+// with native counts the check is conservative: the powers of the map cover every starting
+// timer, so an overflow may be reported a few days before the actual count overflows.
+fn population_after<T: Count>(timers: &[usize], lifecycle: &Lifecycle, days: u64) -> T {
+    let mut fish_pipeline: Matrix<T> = fill_pipeline(timers, lifecycle)
+        .into_iter()
+        .map(|count| vec![T::from_u64(count as u64)])
+        .collect();
+
+    let mut step = transition_matrix(lifecycle, fish_pipeline.len());
+    let mut days = days;
+    while days > 0 {
+        if days & 1 == 1 {
+            fish_pipeline = mul_matrix(&step, &fish_pipeline);
+        }
+        days >>= 1;
+        if days > 0 {
+            step = mul_matrix(&step, &step);
+        }
+    }
+
+    fish_pipeline
+        .iter()
+        .fold(T::from_u64(0), |sum, count| sum.add(&count[0]))
 }
 
 pub fn day06() {
@@ -56,12 +413,49 @@ pub fn day06() {
     );
 
     println!("Puzzle 1:");
-    let fishie_count = puzzle1(&input, 80);
+    let fishie_count = puzzle1(&input, 80).expect("too many fishies");
     println!("After 80 days, there are {} fishies", fishie_count);
 
     println!("Puzzle 2:");
-    let fishie_count256 = puzzle2(&input, 256);
+    let fishie_count256 = puzzle2(&input, 256).expect("too many fishies");
     println!("After 256 days, there are {} fishies", fishie_count256);
+
+    // a made-up second species that is slower to grow up but reproduces more often
+    let school = [
+        Species {
+            lifecycle: LANTERNFISH,
+            timers: input.clone(),
+        },
+        Species {
+            lifecycle: Lifecycle {
+                reset_timer: 4,
+                newborn_timer: 12,
+            },
+            timers: input.clone(),
+        },
+    ];
+    println!(
+        "Sharing the sea with a second species, there are {} fishies after 256 days",
+        school_size(&school, 256, Model::Pipeline).expect("too many fishies")
+    );
+
+    match puzzle2(&input, 500) {
+        Some(count) => println!("After 500 days, there are {} fishies", count),
+        None => println!("After 500 days, there are too many fishies to count"),
+    }
+    println!(
+        "After 1000 days, there are exactly {} fishies",
+        population_after::<BigUint>(&input, &LANTERNFISH, 1000)
+    );
+    println!(
+        "After 10^15 days, there are {} fishies",
+        population_after::<Modular<1_000_000_007>>(&input, &LANTERNFISH, 10u64.pow(15))
+    );
+
+    if let Some(dir) = options::get().export_dir("day06") {
+        let timeline = timeline(&input, &LANTERNFISH, 0..=256).expect("too many fishies");
+        write_timeline(&dir, &timeline).expect("could not write the fish timeline");
+    }
 }
 
 #[cfg(test)]
@@ -72,34 +466,219 @@ mod tests {
 
     #[test]
     fn input_test() {
-        let input = read_input(&EXAMPLE_INPUT);
+        let input = read_input(EXAMPLE_INPUT);
         assert_eq!(input, vec![3, 4, 3, 1, 2]);
     }
 
     #[test]
     fn puzzle1_example() {
-        let input = read_input(&EXAMPLE_INPUT);
+        let input = read_input(EXAMPLE_INPUT);
 
         let mut fishies = input.clone();
-        let mut new_fishies = age_fishies(&mut fishies);
+        let mut new_fishies = age_fishies(&mut fishies, &LANTERNFISH);
         assert_eq!(new_fishies, 0);
         assert_eq!(fishies, vec![2, 3, 2, 0, 1]);
 
-        new_fishies = age_fishies(&mut fishies);
+        new_fishies = age_fishies(&mut fishies, &LANTERNFISH);
         assert_eq!(new_fishies, 1);
         assert_eq!(fishies, vec![1, 2, 1, 6, 0]);
 
         let fishie_count_18 = puzzle1(&input, 18);
-        assert_eq!(fishie_count_18, 26);
+        assert_eq!(fishie_count_18, Some(26));
 
         let fishie_count_80 = puzzle1(&input, 80);
-        assert_eq!(fishie_count_80, 5934);
+        assert_eq!(fishie_count_80, Some(5934));
     }
 
     #[test]
     fn puzzle2_example() {
-        let input = read_input(&EXAMPLE_INPUT);
+        let input = read_input(EXAMPLE_INPUT);
         let fishie_count_256 = puzzle2(&input, 256);
-        assert_eq!(fishie_count_256, 26984457539);
+        assert_eq!(fishie_count_256, Some(26984457539));
+    }
+
+    #[test]
+    fn pipeline_overflow() {
+        // the counts stop fitting into 64 bits within 600 days, and must not wrap around
+        assert_eq!(puzzle2(&[5, 5, 5], 600), None);
+        let last_day = (400..600)
+            .take_while(|&days| puzzle2(&[5, 5, 5], days).is_some())
+            .last()
+            .unwrap();
+        assert_eq!(
+            puzzle2(&[5, 5, 5], last_day).map(|count| count as u64),
+            population_after::<Option<u64>>(&[5, 5, 5], &LANTERNFISH, last_day as u64)
+        );
+
+        assert_eq!(timeline(&[5, 5, 5], &LANTERNFISH, 0..=600), None);
+        let school = lanternfish(&[5, 5, 5]);
+        assert_eq!(school_size(&school, 600, Model::Pipeline), None);
+    }
+
+    #[test]
+    fn models_agree() {
+        let input = read_input(EXAMPLE_INPUT);
+        let lifecycles = [
+            LANTERNFISH,
+            Lifecycle {
+                reset_timer: 2,
+                newborn_timer: 5,
+            },
+            // newborns that are quicker than their parents
+            Lifecycle {
+                reset_timer: 7,
+                newborn_timer: 1,
+            },
+            // doubles every day, which keeps the naive model to a few weeks
+            Lifecycle {
+                reset_timer: 0,
+                newborn_timer: 0,
+            },
+        ];
+
+        for lifecycle in lifecycles {
+            let school = [Species {
+                lifecycle,
+                timers: input.clone(),
+            }];
+            for days in [0, 1, 2, 7, 12, 18] {
+                assert_eq!(
+                    school_size(&school, days, Model::Naive),
+                    school_size(&school, days, Model::Pipeline)
+                );
+            }
+        }
+
+        let school: Vec<Species> = lifecycles
+            .iter()
+            .take(3)
+            .map(|&lifecycle| Species {
+                lifecycle,
+                timers: input.clone(),
+            })
+            .collect();
+        assert_eq!(
+            school_size(&school, 30, Model::Naive),
+            school_size(&school, 30, Model::Pipeline)
+        );
+        assert_eq!(school_size(&school[..1], 80, Model::Pipeline), Some(5934));
+    }
+
+    #[test]
+    fn exponentiation_test() {
+        let input = read_input(EXAMPLE_INPUT);
+        for days in [0, 1, 18, 80, 256] {
+            let expected = puzzle2(&input, days as usize).unwrap() as u64;
+            assert_eq!(
+                population_after::<Option<u64>>(&input, &LANTERNFISH, days),
+                Some(expected)
+            );
+            assert_eq!(
+                population_after::<Modular<1_000_000_007>>(&input, &LANTERNFISH, days),
+                Modular(expected % 1_000_000_007)
+            );
+            assert_eq!(
+                population_after::<BigUint>(&input, &LANTERNFISH, days).to_string(),
+                expected.to_string()
+            );
+        }
+
+        let lifecycle = Lifecycle {
+            reset_timer: 2,
+            newborn_timer: 5,
+        };
+        for days in [3, 17, 40] {
+            assert_eq!(
+                population_after::<Option<u64>>(&input, &lifecycle, days),
+                simulate_pipeline(&input, &lifecycle, days as usize).map(|count| count as u64)
+            );
+        }
+    }
+
+    #[test]
+    fn huge_day_counts() {
+        let input = read_input(EXAMPLE_INPUT);
+        assert_eq!(
+            population_after::<Option<u64>>(&input, &LANTERNFISH, 1000),
+            None
+        );
+
+        // the exact count and the modular one have to agree way past the native range
+        let mut exact = population_after::<BigUint>(&input, &LANTERNFISH, 1000);
+        assert!(exact.to_string().len() > 35);
+        let modular = population_after::<Modular<1_000_000_007>>(&input, &LANTERNFISH, 1000);
+        assert_eq!(exact.div_rem(1_000_000_007) as u64, modular.0);
+
+        // every fish is replaced by one 7 days later and one 9 days later, so the counts follow
+        // f(n) = f(n - 7) + f(n - 9)
+        let n = 10u64.pow(15);
+        let f = |days| population_after::<Modular<1_000_000_007>>(&input, &LANTERNFISH, days);
+        assert_eq!(f(n), f(n - 7).add(&f(n - 9)));
+    }
+
+    #[test]
+    fn big_uint_test() {
+        assert_eq!(BigUint::from_u64(0).to_string(), "0");
+        assert_eq!(
+            BigUint::from_u64(u64::MAX).to_string(),
+            u64::MAX.to_string()
+        );
+
+        let big = BigUint::from_u64(u64::MAX).mul(&BigUint::from_u64(u64::MAX));
+        assert_eq!(
+            big.to_string(),
+            (u64::MAX as u128 * u64::MAX as u128).to_string()
+        );
+        let sum = big.add(&BigUint::from_u64(u64::MAX));
+        assert_eq!(
+            sum.to_string(),
+            (u64::MAX as u128 * u64::MAX as u128 + u64::MAX as u128).to_string()
+        );
+        assert_eq!(BigUint::from_u64(1_000_000_000).to_string(), "1000000000");
+    }
+
+    #[test]
+    fn timeline_test() {
+        let input = read_input(EXAMPLE_INPUT);
+        let stats = timeline(&input, &LANTERNFISH, 0..=18).unwrap();
+        assert_eq!(stats.len(), 19);
+        assert_eq!(
+            stats[0],
+            DayStats {
+                day: 0,
+                population: 5,
+                timers: vec![0, 1, 1, 2, 1, 0, 0, 0, 0],
+            }
+        );
+        // 1,2,1,6,0,8 after the second day
+        assert_eq!(stats[2].timers, vec![1, 2, 1, 0, 0, 0, 1, 0, 1]);
+        assert_eq!(stats[18].population, 26);
+
+        let stats = timeline(&input, &LANTERNFISH, 79..=80).unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[1].day, 80);
+        assert_eq!(stats[1].population, 5934);
+    }
+
+    #[test]
+    fn export_test() {
+        let input = read_input(EXAMPLE_INPUT);
+        let stats = timeline(&input, &LANTERNFISH, 1..=2).unwrap();
+
+        assert_eq!(
+            export_timeline(&stats, Format::Csv),
+            "day,population,timer_0,timer_1,timer_2,timer_3,timer_4,timer_5,timer_6,timer_7,timer_8
+1,5,1,1,2,1,0,0,0,0,0
+2,6,1,2,1,0,0,0,1,0,1
+"
+        );
+        assert_eq!(
+            export_timeline(&stats, Format::Json),
+            r#"[
+  {"day": 1, "population": 5, "timers": [1,1,2,1,0,0,0,0,0]},
+  {"day": 2, "population": 6, "timers": [1,2,1,0,0,0,1,0,1]}
+]
+"#
+        );
     }
 }
@@ -1,62 +1,252 @@
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::{fs, io};
+
+use crate::options;
+
 const INPUT: &str = include_str!("../inputs/day07");
 
 fn read_input(input: &str) -> Vec<i32> {
-    input.split(',').map(|s| s.parse().unwrap()).collect()
+    input
+        .trim()
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().unwrap())
+        .collect()
 }
 
-fn median(data: &mut [i32]) -> i32 {
-    let even = data.len() % 2 == 0;
-    let (_, median, above) = data.select_nth_unstable(data.len() / 2 - 1);
-    if even {
-        let median_upper = above.iter().min().unwrap();
-        (*median + *median_upper) / 2
+// every position in the returned range minimizes the sum of distances: just the middle element
+// for an odd number of crabs, and everything between the two middle ones for an even number
+fn median(data: &mut [i32]) -> Option<RangeInclusive<i32>> {
+    if data.is_empty() {
+        return None;
+    }
+
+    let middle = data.len() / 2;
+    if data.len().is_multiple_of(2) {
+        let (_, lower, above) = data.select_nth_unstable(middle - 1);
+        let upper = above.iter().min().unwrap();
+        Some(*lower..=*upper)
     } else {
-        *median
+        let (_, median, _) = data.select_nth_unstable(middle);
+        Some(*median..=*median)
+    }
+}
+
+enum CostFn {
+    // one fuel per step
+    Linear,
+    // one more fuel for every further step
+    Triangular,
+    Quadratic,
+    // fuel for a distance, has to be convex and non-decreasing for the optimizer to work
+    Custom(fn(i64) -> i64),
+}
+
+impl CostFn {
+    fn fuel(&self, distance: i64) -> i64 {
+        match self {
+            CostFn::Linear => distance,
+            CostFn::Triangular => distance * (distance + 1) / 2,
+            CostFn::Quadratic => distance * distance,
+            CostFn::Custom(f) => f(distance),
+        }
     }
 }
 
-fn puzzle1(input: &[i32]) -> i32 {
-    let mut crab_positions = input.to_vec();
-    let median = median(&mut crab_positions);
-    let fuel_needed: i32 = crab_positions
+#[derive(Debug, PartialEq)]
+struct Alignment {
+    position: i32,
+    fuel: i64,
+}
+
+// every position in `positions` takes the same, lowest amount of fuel
+#[derive(Debug, PartialEq)]
+struct LinearAlignment {
+    positions: RangeInclusive<i32>,
+    fuel: i64,
+}
+
+fn total_fuel(crab_positions: &[i32], position: i32, cost: &CostFn) -> i64 {
+    crab_positions
         .iter()
-        .map(|position| (median - position).abs())
-        .sum();
-    fuel_needed
+        .map(|&crab| cost.fuel((crab as i64 - position as i64).abs()))
+        .sum()
 }
 
-fn partial_sums(len: usize) -> Vec<i32> {
-    let mut accumulator = 0;
-    (0..len as i32)
-        .map(|index| {
-            accumulator += index;
-            accumulator
+fn cheapest_of(
+    crab_positions: &[i32],
+    candidates: impl Iterator<Item = i32>,
+    cost: &CostFn,
+) -> Alignment {
+    candidates
+        .map(|position| Alignment {
+            position,
+            fuel: total_fuel(crab_positions, position, cost),
         })
-        .collect()
+        .min_by_key(|alignment| (alignment.fuel, alignment.position))
+        .unwrap()
+}
+
+// the total fuel is convex in the position, so a third of the range can be dropped every round.
+// the bounds are kept in i64 since the outermost crabs can be further apart than i32::MAX.
+fn ternary_search(crab_positions: &[i32], cost: &CostFn) -> Alignment {
+    let mut low = *crab_positions.iter().min().unwrap() as i64;
+    let mut high = *crab_positions.iter().max().unwrap() as i64;
+
+    while high - low > 2 {
+        let third = (high - low) / 3;
+        let (left, right) = ((low + third) as i32, (high - third) as i32);
+        if total_fuel(crab_positions, left, cost) <= total_fuel(crab_positions, right, cost) {
+            high = right as i64;
+        } else {
+            low = left as i64;
+        }
+    }
+
+    cheapest_of(crab_positions, low as i32..=high as i32, cost)
+}
+
+// the median minimizes the sum of distances, so all of the optimal positions are known for linear
+// costs. None if there are no crabs.
+fn align_linear(crab_positions: &[i32]) -> Option<LinearAlignment> {
+    let positions = median(&mut crab_positions.to_vec())?;
+    let fuel = total_fuel(crab_positions, *positions.start(), &CostFn::Linear);
+    Some(LinearAlignment { positions, fuel })
 }
 
-fn puzzle2(crab_positions: &[i32]) -> i32 {
-    let len = *crab_positions.iter().max().unwrap() as usize + 1;
-    let mut fuel_consumptions = vec![0; len];
-    let partial_sums = partial_sums(len);
+// the squared distances are minimized by the mean, and triangular costs are half of those plus
+// half of the linear ones, which puts the optimum within half a step of the mean.
+// for linear costs, this is the lowest of all optimal positions. None if there are no crabs.
+fn align(crab_positions: &[i32], cost: &CostFn) -> Option<Alignment> {
+    if crab_positions.is_empty() {
+        return None;
+    }
 
-    for crab_position in crab_positions {
-        for position in 0..fuel_consumptions.len() {
-            fuel_consumptions[position] +=
-                partial_sums[(crab_position - position as i32).abs() as usize];
+    let alignment = match cost {
+        CostFn::Linear => {
+            let LinearAlignment { positions, fuel } = align_linear(crab_positions)?;
+            Alignment {
+                position: *positions.start(),
+                fuel,
+            }
+        }
+        CostFn::Triangular | CostFn::Quadratic => {
+            let sum: i64 = crab_positions.iter().map(|&p| p as i64).sum();
+            let mean = sum.div_euclid(crab_positions.len() as i64);
+            // the optimum never lies beyond the outermost crabs, which keeps this within i32
+            let min = *crab_positions.iter().min().unwrap() as i64;
+            let max = *crab_positions.iter().max().unwrap() as i64;
+            let candidates = (mean - 1).max(min) as i32..=(mean + 2).min(max) as i32;
+            cheapest_of(crab_positions, candidates, cost)
         }
+        CostFn::Custom(_) => ternary_search(crab_positions, cost),
+    };
+    Some(alignment)
+}
+
+// fuel needed for every position between the outermost crabs
+fn fuel_curve(crab_positions: &[i32], cost: &CostFn) -> Vec<Alignment> {
+    let (Some(&min), Some(&max)) = (crab_positions.iter().min(), crab_positions.iter().max())
+    else {
+        return Vec::new();
+    };
+    (min..=max)
+        .map(|position| Alignment {
+            position,
+            fuel: total_fuel(crab_positions, position, cost),
+        })
+        .collect()
+}
+
+fn cheapest(curve: &[Alignment]) -> Option<&Alignment> {
+    curve
+        .iter()
+        .min_by_key(|alignment| (alignment.fuel, alignment.position))
+}
+
+fn curve_to_csv(curve: &[Alignment]) -> String {
+    let minimum = cheapest(curve).map(|alignment| alignment.position);
+    let mut csv = String::from("position,fuel,minimum\n");
+    for alignment in curve {
+        csv += &format!(
+            "{},{},{}\n",
+            alignment.position,
+            alignment.fuel,
+            Some(alignment.position) == minimum
+        );
+    }
+    csv
+}
+
+// squeezes the curve into at most `width` bars, each showing the lowest fuel of the positions it
+// covers, with a caret below the bar that holds the minimum
+fn sparkline(curve: &[Alignment], width: usize) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let Some(minimum) = cheapest(curve) else {
+        return String::new();
+    };
+    let per_bar = curve.len().div_ceil(width.max(1));
+    let bars: Vec<i64> = curve
+        .chunks(per_bar)
+        .map(|chunk| chunk.iter().map(|alignment| alignment.fuel).min().unwrap())
+        .collect();
+
+    let low = minimum.fuel;
+    let high = *bars.iter().max().unwrap();
+    let mut line: String = bars
+        .iter()
+        .map(|&fuel| {
+            let level = if high == low {
+                0
+            } else {
+                (fuel - low) as i128 * (BARS.len() - 1) as i128 / (high - low) as i128
+            };
+            BARS[level as usize]
+        })
+        .collect();
+
+    let marker = (minimum.position - curve[0].position) as usize / per_bar;
+    line += &format!("\n{}^\n", " ".repeat(marker));
+    line
+}
+
+fn write_curves(dir: &Path, curves: &[(&str, Vec<Alignment>)]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for (name, curve) in curves {
+        fs::write(dir.join(format!("{}.csv", name)), curve_to_csv(curve))?;
     }
+    Ok(())
+}
+
+fn puzzle1(input: &[i32]) -> i64 {
+    align_linear(input).expect("no crabs").fuel
+}
 
-    *fuel_consumptions.iter().min().unwrap()
+fn puzzle2(crab_positions: &[i32]) -> i64 {
+    align(crab_positions, &CostFn::Triangular)
+        .expect("no crabs")
+        .fuel
 }
 
 pub fn day07() {
     println!("\nDay 7:");
-    let crab_positions = read_input(&INPUT);
+    let crab_positions = read_input(INPUT);
 
     println!("Puzzle 1:");
     let fuel_needed = puzzle1(&crab_positions);
     println!("The amount of fuel needed is {}", fuel_needed);
+    let positions = align_linear(&crab_positions).expect("no crabs").positions;
+    if positions.start() == positions.end() {
+        println!("Only position {} takes that little fuel", positions.start());
+    } else {
+        println!(
+            "Every position from {} to {} takes that little fuel",
+            positions.start(),
+            positions.end()
+        );
+    }
 
     println!("Puzzle 2:");
     let fuel_needed_2 = puzzle2(&crab_positions);
@@ -64,6 +254,29 @@ pub fn day07() {
         "The amount of fuel needed (real crab engineering) is {}",
         fuel_needed_2
     );
+
+    let costs = [
+        ("linear", CostFn::Linear),
+        ("triangular", CostFn::Triangular),
+        ("quadratic", CostFn::Quadratic),
+        ("cubic", CostFn::Custom(|distance| distance.pow(3))),
+    ];
+    let mut curves = Vec::new();
+    for (name, cost) in &costs {
+        let Alignment { position, fuel } = align(&crab_positions, cost).expect("no crabs");
+        println!(
+            "With {} costs, aligning at {} takes {} fuel",
+            name, position, fuel
+        );
+
+        let curve = fuel_curve(&crab_positions, cost);
+        print!("{}", sparkline(&curve, 64));
+        curves.push((*name, curve));
+    }
+
+    if let Some(dir) = options::get().export_dir("day07") {
+        write_curves(&dir, &curves).expect("could not write the fuel curves");
+    }
 }
 
 #[cfg(test)]
@@ -74,22 +287,175 @@ mod tests {
 
     #[test]
     fn median_test() {
-        let mut crab_positions = read_input(&EXAMPLE_INPUT);
+        let mut crab_positions = read_input(EXAMPLE_INPUT);
         let median = median(&mut crab_positions);
-        assert_eq!(median, 2);
+        assert_eq!(median, Some(2..=2));
+
+        assert_eq!(super::median(&mut []), None);
+        assert_eq!(super::median(&mut [3]), Some(3..=3));
+        assert_eq!(super::median(&mut [5, -1, 3]), Some(3..=3));
+        assert_eq!(super::median(&mut [10, -7]), Some(-7..=10));
+        assert_eq!(super::median(&mut [4, 1, -2, 8]), Some(1..=4));
     }
 
     #[test]
     fn puzzle1_example() {
-        let mut crab_positions = read_input(&EXAMPLE_INPUT);
-        let fuel_needed = puzzle1(&mut crab_positions);
+        let crab_positions = read_input(EXAMPLE_INPUT);
+        let fuel_needed = puzzle1(&crab_positions);
         assert_eq!(fuel_needed, 37);
     }
 
     #[test]
     fn puzzle2_example() {
-        let crab_positions = read_input(&EXAMPLE_INPUT);
+        let crab_positions = read_input(EXAMPLE_INPUT);
         let fuel_needed = puzzle2(&crab_positions);
         assert_eq!(fuel_needed, 168);
     }
+
+    // checks every position in range, which is what the optimizer is trying to avoid
+    fn brute_force(crab_positions: &[i32], cost: &CostFn) -> Alignment {
+        let curve = fuel_curve(crab_positions, cost);
+        let cheapest = cheapest(&curve).unwrap();
+        Alignment {
+            position: cheapest.position,
+            fuel: cheapest.fuel,
+        }
+    }
+
+    #[test]
+    fn align_test() {
+        let crab_positions = read_input(EXAMPLE_INPUT);
+        assert_eq!(
+            align(&crab_positions, &CostFn::Linear),
+            Some(Alignment {
+                position: 2,
+                fuel: 37
+            })
+        );
+        assert_eq!(
+            align(&crab_positions, &CostFn::Triangular),
+            Some(Alignment {
+                position: 5,
+                fuel: 168
+            })
+        );
+        assert_eq!(align(&[], &CostFn::Linear), None);
+
+        let costs = [
+            CostFn::Linear,
+            CostFn::Triangular,
+            CostFn::Quadratic,
+            CostFn::Custom(|distance| distance.pow(3)),
+            CostFn::Custom(|distance| (distance - 3).max(0)),
+        ];
+        let inputs = [
+            EXAMPLE_INPUT,
+            INPUT,
+            "1",
+            "-5,-3,100",
+            "-5,-3,100,7",
+            "0,0,0,1000",
+            "0,0,0,0,1000",
+            "-20,-19,-3",
+            "2147483646,2147483647",
+            "-2147483648,-2147483647,-2147483647",
+        ];
+        for input in inputs {
+            let crab_positions = read_input(input);
+            for cost in &costs {
+                assert_eq!(
+                    align(&crab_positions, cost).unwrap().fuel,
+                    brute_force(&crab_positions, cost).fuel
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn far_apart() {
+        // the crabs are further apart than fits in an i32, too far for a brute force
+        let crab_positions = [-2_000_000_000, 0, 2_000_000_000];
+        let cost = CostFn::Custom(|distance| (distance - 3).max(0));
+        let alignment = align(&crab_positions, &cost).unwrap();
+        assert!(alignment.position.abs() <= 3);
+        assert_eq!(alignment.fuel, 4_000_000_000 - 6);
+
+        let alignment = align(&crab_positions, &CostFn::Linear).unwrap();
+        assert_eq!(alignment.position, 0);
+        assert_eq!(alignment.fuel, 4_000_000_000);
+    }
+
+    #[test]
+    fn linear_interval() {
+        // any position between the two middle crabs is just as good
+        let crab_positions = read_input("-4,0,6,9");
+        let LinearAlignment { positions, fuel } = align_linear(&crab_positions).unwrap();
+        assert_eq!(positions, 0..=6);
+        assert_eq!(fuel, 19);
+
+        let optimal = total_fuel(&crab_positions, 0, &CostFn::Linear);
+        for position in positions {
+            assert_eq!(
+                total_fuel(&crab_positions, position, &CostFn::Linear),
+                optimal
+            );
+        }
+        assert!(total_fuel(&crab_positions, -1, &CostFn::Linear) > optimal);
+        assert!(total_fuel(&crab_positions, 7, &CostFn::Linear) > optimal);
+
+        assert_eq!(
+            align_linear(&read_input(EXAMPLE_INPUT)),
+            Some(LinearAlignment {
+                positions: 2..=2,
+                fuel: 37
+            })
+        );
+        assert_eq!(align_linear(&[]), None);
+    }
+
+    #[test]
+    fn curve_export() {
+        let crab_positions = read_input(EXAMPLE_INPUT);
+        let curve = fuel_curve(&crab_positions, &CostFn::Linear);
+        assert_eq!(curve.len(), 17);
+        assert_eq!(
+            curve[2],
+            Alignment {
+                position: 2,
+                fuel: 37
+            }
+        );
+
+        let csv = curve_to_csv(&curve);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 18);
+        assert_eq!(lines[0], "position,fuel,minimum");
+        assert_eq!(lines[1], "0,49,false");
+        assert_eq!(lines[3], "2,37,true");
+        assert_eq!(csv.matches("true").count(), 1);
+
+        assert_eq!(fuel_curve(&[], &CostFn::Linear), Vec::new());
+        assert_eq!(curve_to_csv(&[]), "position,fuel,minimum\n");
+    }
+
+    #[test]
+    fn sparkline_test() {
+        let crab_positions = read_input("0,4");
+        let curve = fuel_curve(&crab_positions, &CostFn::Quadratic);
+        // 16, 10, 8, 10, 16
+        assert_eq!(sparkline(&curve, 10), "█▂▁▂█\n  ^\n");
+        // pairs of positions share a bar
+        assert_eq!(sparkline(&curve, 3), "▂▁█\n ^\n");
+
+        let crab_positions = read_input(EXAMPLE_INPUT);
+        let curve = fuel_curve(&crab_positions, &CostFn::Triangular);
+        let line = sparkline(&curve, 64);
+        let (bars, marker) = line.split_once('\n').unwrap();
+        assert_eq!(bars.chars().count(), 17);
+        assert_eq!(marker, "     ^\n");
+        assert_eq!(bars.chars().nth(5), Some('▁'));
+
+        assert_eq!(sparkline(&fuel_curve(&[7], &CostFn::Linear), 64), "▁\n^\n");
+        assert_eq!(sparkline(&[], 64), "");
+    }
 }
@@ -1,6 +1,109 @@
 const INPUT: &str = include_str!("../inputs/day08");
 
-fn puzzle1(note_entries: &str) -> usize {
+// a display font: which segments each symbol lights up, with segments named from 'a' up to 'h'.
+// the value of a symbol is its position, so a font with 16 glyphs reads numbers in hex.
+struct Font {
+    segment_count: usize,
+    // segment a is bit 0
+    glyphs: Vec<(char, u8)>,
+    // position of the glyph for every combination of segments
+    lookup: [Option<u8>; 256],
+    // see `signature`, None if two segments can't be told apart that way
+    signatures: Option<Vec<u128>>,
+}
+
+impl Font {
+    fn new(glyphs: &[(char, &str)]) -> Font {
+        let glyphs: Vec<(char, u8)> = glyphs
+            .iter()
+            .map(|&(symbol, segments)| {
+                let mask = parse_pattern(segments).expect("segments are named a to h");
+                (symbol, mask)
+            })
+            .collect();
+
+        let all_segments = glyphs.iter().fold(0, |all, &(_, mask)| all | mask);
+        let segment_count = 8 - all_segments.leading_zeros() as usize;
+
+        let mut lookup = [None; 256];
+        for (i, &(_, mask)) in glyphs.iter().enumerate() {
+            lookup[mask as usize] = Some(i as u8);
+        }
+
+        let masks: Vec<u8> = glyphs.iter().map(|&(_, mask)| mask).collect();
+        let signatures: Vec<u128> = (0..segment_count)
+            .map(|segment| signature(&masks, segment))
+            .collect();
+        let distinct = signatures
+            .iter()
+            .enumerate()
+            .all(|(i, s)| !signatures[..i].contains(s));
+
+        Font {
+            segment_count,
+            glyphs,
+            lookup,
+            signatures: distinct.then_some(signatures),
+        }
+    }
+
+    // the seven segment digits from the puzzle
+    fn digits() -> Font {
+        Font::new(&DIGITS)
+    }
+
+    fn hex() -> Font {
+        Font::new(&[DIGITS.as_slice(), &HEX_LETTERS].concat())
+    }
+
+    // symbols that are the only ones with their number of segments
+    fn identifiable_by_count(&self) -> Vec<char> {
+        self.glyphs
+            .iter()
+            .filter(|(_, segments)| {
+                self.glyphs
+                    .iter()
+                    .filter(|(_, other)| other.count_ones() == segments.count_ones())
+                    .count()
+                    == 1
+            })
+            .map(|&(symbol, _)| symbol)
+            .collect()
+    }
+}
+
+const DIGITS: [(char, &str); 10] = [
+    ('0', "abcefg"),
+    ('1', "cf"),
+    ('2', "acdeg"),
+    ('3', "acdfg"),
+    ('4', "bcdf"),
+    ('5', "abdfg"),
+    ('6', "abdefg"),
+    ('7', "acf"),
+    ('8', "abcdefg"),
+    ('9', "abcdfg"),
+];
+
+const HEX_LETTERS: [(char, &str); 6] = [
+    ('A', "abcdef"),
+    ('b', "bdefg"),
+    ('C', "abeg"),
+    ('d', "cdefg"),
+    ('E', "abdeg"),
+    ('F', "abde"),
+];
+
+fn puzzle1(note_entries: &str, font: &Font) -> usize {
+    let lengths: Vec<usize> = font
+        .identifiable_by_count()
+        .iter()
+        .map(|&symbol| {
+            let (_, segments) = font.glyphs.iter().find(|(s, _)| *s == symbol).unwrap();
+            segments.count_ones() as usize
+        })
+        .collect();
+
     note_entries
         .lines()
         .flat_map(|line| {
@@ -8,80 +111,188 @@ fn puzzle1(note_entries: &str) -> usize {
                 .skip(1)
                 .flat_map(|output_values| output_values.split_whitespace())
         })
-        .filter(|&output| [2, 3, 4, 7].contains(&output.len()))
+        .filter(|&output| lengths.contains(&output.len()))
         .count()
 }
 
-use std::collections::HashSet;
+// wiring[wire] is the segment the wire is connected to, both counted from 0 for 'a'
+type Wiring = Vec<usize>;
 
-fn solve_line(line: &str) -> i32 {
+#[derive(Debug, PartialEq)]
+enum DecodeError {
+    // no wiring turns every pattern into a glyph
+    Inconsistent,
+    // this many wirings turn every pattern into a glyph
+    Ambiguous(usize),
+}
+
+// wire a is bit 0, None for anything that isn't a wire
+fn parse_pattern(pattern: &str) -> Option<u8> {
+    pattern.bytes().try_fold(0, |mask, wire| match wire {
+        b'a'..=b'h' => Some(mask | 1 << (wire - b'a')),
+        _ => None,
+    })
+}
+
+fn parse_entry(line: &str) -> Result<(Vec<u8>, Vec<u8>), DecodeError> {
     let mut patterns = line.split_whitespace();
-    let unique_patterns: Vec<HashSet<_>> = patterns
+    let unique_patterns = patterns
         .by_ref()
         .take_while(|s| *s != "|")
-        .map(|s| s.chars().collect())
+        .map(parse_pattern)
+        .collect::<Option<_>>();
+    let output = patterns.map(parse_pattern).collect::<Option<_>>();
+    unique_patterns.zip(output).ok_or(DecodeError::Inconsistent)
+}
+
+// the position of the glyph the pattern shows
+fn decode(pattern: u8, wiring: &Wiring, font: &Font) -> Option<usize> {
+    let mut segments = 0;
+    for wire in 0..8 {
+        if pattern & 1 << wire != 0 {
+            segments |= 1 << wiring.get(wire)?;
+        }
+    }
+    font.lookup[segments as usize].map(usize::from)
+}
+
+// how many patterns of each size a segment (or wire) is part of, one byte per size.
+// this doesn't change when wires get swapped around.
+fn signature(patterns: &[u8], bit: usize) -> u128 {
+    patterns
+        .iter()
+        .filter(|&&pattern| pattern & 1 << bit != 0)
+        .map(|pattern| 1 << (8 * pattern.count_ones()))
+        .sum()
+}
+
+// all orderings of 0..n, in lexicographic order
+fn wirings(n: usize) -> Vec<Wiring> {
+    let mut wiring: Wiring = (0..n).collect();
+    let mut wirings = vec![wiring.clone()];
+    // next permutation: find the last ascent, swap in the next larger element, reverse the tail
+    while let Some(i) = (0..n.saturating_sub(1))
+        .rev()
+        .find(|&i| wiring[i] < wiring[i + 1])
+    {
+        let j = (i + 1..n).rev().find(|&j| wiring[j] > wiring[i]).unwrap();
+        wiring.swap(i, j);
+        wiring[i + 1..].reverse();
+        wirings.push(wiring.clone());
+    }
+    wirings
+}
+
+// if the unique patterns are exactly the glyphs, every wire has the signature of its segment
+fn wiring_from_signatures(unique_patterns: &[u8], font: &Font) -> Option<Wiring> {
+    let signatures = font.signatures.as_ref()?;
+    if unique_patterns.len() != font.glyphs.len() {
+        return None;
+    }
+
+    let wiring: Wiring = (0..font.segment_count)
+        .map(|wire| {
+            let wire_signature = signature(unique_patterns, wire);
+            signatures.iter().position(|&s| s == wire_signature)
+        })
+        .collect::<Option<_>>()?;
+
+    let used = wiring
+        .iter()
+        .fold(0u8, |used, &segment| used | 1 << segment);
+    (used.count_ones() as usize == font.segment_count).then_some(wiring)
+}
+
+// tries every wiring, i.e. 5040 of them for seven segments
+fn search_wiring(patterns: &[u8], font: &Font) -> Result<Wiring, DecodeError> {
+    let mut fitting: Vec<Wiring> = wirings(font.segment_count)
+        .into_iter()
+        .filter(|wiring| {
+            patterns
+                .iter()
+                .all(|&pattern| decode(pattern, wiring, font).is_some())
+        })
         .collect();
-    let numbers: Vec<HashSet<_>> = patterns.map(|s| s.chars().collect()).collect();
-
-    let one = unique_patterns.iter().find(|p| p.len() == 2).unwrap();
-    let four = unique_patterns.iter().find(|p| p.len() == 4).unwrap();
-
-    let mut result = 0;
-    for number in &numbers {
-        result *= 10;
-
-        result += match number.len() {
-            2 => 1,
-            3 => 7,
-            4 => 4,
-            7 => 8,
-            5 => {
-                if (number - one).len() == 3 {
-                    3
-                } else if (number - four).len() == 2 {
-                    5
-                } else {
-                    2
-                }
-            }
-            6 => {
-                if (one - number).len() == 1 {
-                    6
-                } else if (four - number).len() == 0 {
-                    9
-                } else {
-                    0
-                }
-            }
-            _ => panic!("that's not a valid 7 segment number!"),
+
+    match fitting.len() {
+        1 => Ok(fitting.pop().unwrap()),
+        0 => Err(DecodeError::Inconsistent),
+        n => Err(DecodeError::Ambiguous(n)),
+    }
+}
+
+// `patterns` starts with the unique patterns
+fn find_wiring(patterns: &[u8], unique_count: usize, font: &Font) -> Result<Wiring, DecodeError> {
+    if let Some(wiring) = wiring_from_signatures(&patterns[..unique_count], font) {
+        if patterns
+            .iter()
+            .all(|&pattern| decode(pattern, &wiring, font).is_some())
+        {
+            return Ok(wiring);
         }
     }
+    search_wiring(patterns, font)
+}
+
+fn solve_line(line: &str, font: &Font) -> Result<u64, DecodeError> {
+    let (mut patterns, output) = parse_entry(line)?;
+    let unique_count = patterns.len();
+    patterns.extend(&output);
+    let wiring = find_wiring(&patterns, unique_count, font)?;
+
+    let base = font.glyphs.len() as u64;
+    Ok(output.iter().fold(0, |result, &number| {
+        result * base + decode(number, &wiring, font).unwrap() as u64
+    }))
+}
 
-    result
+fn decode_all(note_entries: &str, font: &Font) -> Vec<Result<u64, DecodeError>> {
+    note_entries
+        .lines()
+        .map(|line| solve_line(line, font))
+        .collect()
 }
 
-fn puzzle2(note_entries: &str) -> i32 {
-    note_entries.lines().map(|line| solve_line(line)).sum()
+fn puzzle2(note_entries: &str, font: &Font) -> Result<u64, DecodeError> {
+    decode_all(note_entries, font).into_iter().sum()
 }
 
 pub fn day08() {
     println!("\nDay 8:");
 
     println!("Puzzle 1:");
-    let num_easy_digits = puzzle1(&INPUT);
+    let digits = Font::digits();
+    let easy_digits: Vec<String> = digits
+        .identifiable_by_count()
+        .iter()
+        .map(char::to_string)
+        .collect();
+    let num_easy_digits = puzzle1(INPUT, &digits);
     println!(
-        "In the output values, the digits 1, 4, 7 and 8 appear {} times",
+        "In the output values, the digits {} appear {} times",
+        easy_digits.join(", "),
         num_easy_digits
     );
 
+    let hex = Font::hex();
+    println!(
+        "In hex, only {:?} can be told apart by their number of segments",
+        hex.identifiable_by_count()
+    );
+
     println!("Puzzle 2:");
-    let sum_of_output_values = puzzle2(&INPUT);
-    println!("The sum of all output values is {}", sum_of_output_values);
+    match puzzle2(INPUT, &digits) {
+        Ok(sum_of_output_values) => {
+            println!("The sum of all output values is {}", sum_of_output_values)
+        }
+        Err(e) => println!("Could not decode all displays: {:?}", e),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::rng::Lcg;
 
     const TEST_INPUT: &str = r"be cfbegad cbdgef fgaecd cgeb fdcge agebfd fecdb fabcd edb | fdgacbe cefdb cefbgd gcbe
     edbfga begcd cbg gc gcadebf fbgde acbgfd abcde gfcbed gfec | fcgedb cgb dgebacf gc
@@ -96,25 +307,180 @@ mod tests {
 
     #[test]
     fn puzzle1_example() {
-        let num_easy_digits = puzzle1(&TEST_INPUT);
+        let num_easy_digits = puzzle1(TEST_INPUT, &Font::digits());
         assert_eq!(num_easy_digits, 26);
     }
 
     #[test]
     fn puzzle2_example() {
+        let digits = Font::digits();
         let mut lines = TEST_INPUT.lines();
-        assert_eq!(solve_line(lines.next().unwrap()), 8394);
-        assert_eq!(solve_line(lines.next().unwrap()), 9781);
-        assert_eq!(solve_line(lines.next().unwrap()), 1197);
-        assert_eq!(solve_line(lines.next().unwrap()), 9361);
-        assert_eq!(solve_line(lines.next().unwrap()), 4873);
-        assert_eq!(solve_line(lines.next().unwrap()), 8418);
-        assert_eq!(solve_line(lines.next().unwrap()), 4548);
-        assert_eq!(solve_line(lines.next().unwrap()), 1625);
-        assert_eq!(solve_line(lines.next().unwrap()), 8717);
-        assert_eq!(solve_line(lines.next().unwrap()), 4315);
-
-        let sum_of_output_values = puzzle2(&TEST_INPUT);
-        assert_eq!(sum_of_output_values, 61229);
+        assert_eq!(solve_line(lines.next().unwrap(), &digits), Ok(8394));
+        assert_eq!(solve_line(lines.next().unwrap(), &digits), Ok(9781));
+        assert_eq!(solve_line(lines.next().unwrap(), &digits), Ok(1197));
+        assert_eq!(solve_line(lines.next().unwrap(), &digits), Ok(9361));
+        assert_eq!(solve_line(lines.next().unwrap(), &digits), Ok(4873));
+        assert_eq!(solve_line(lines.next().unwrap(), &digits), Ok(8418));
+        assert_eq!(solve_line(lines.next().unwrap(), &digits), Ok(4548));
+        assert_eq!(solve_line(lines.next().unwrap(), &digits), Ok(1625));
+        assert_eq!(solve_line(lines.next().unwrap(), &digits), Ok(8717));
+        assert_eq!(solve_line(lines.next().unwrap(), &digits), Ok(4315));
+
+        let sum_of_output_values = puzzle2(TEST_INPUT, &digits);
+        assert_eq!(sum_of_output_values, Ok(61229));
+    }
+
+    #[test]
+    fn wiring_test() {
+        assert_eq!(wirings(7).len(), 5040);
+
+        // the wiring from the puzzle description
+        let line =
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf";
+        let (unique_patterns, _) = parse_entry(line).unwrap();
+        let digits = Font::digits();
+        let wiring = find_wiring(&unique_patterns, 10, &digits).unwrap();
+        assert_eq!(Ok(wiring.clone()), search_wiring(&unique_patterns, &digits));
+        let segments: String = wiring.iter().map(|&s| (b'a' + s as u8) as char).collect();
+        assert_eq!(segments, "cfgabde");
+        assert_eq!(solve_line(line, &digits), Ok(5353));
+    }
+
+    #[test]
+    fn decode_errors() {
+        // 1 and 7 alone don't say much about the wiring
+        let digits = Font::digits();
+        assert_eq!(
+            find_wiring(&masks(&["ab", "dab"]), 2, &digits),
+            Err(DecodeError::Ambiguous(48))
+        );
+        // there is no digit with 4 segments that contains those of a 7
+        assert_eq!(
+            find_wiring(&masks(&["ab", "dab", "abcd"]), 3, &digits),
+            Err(DecodeError::Inconsistent)
+        );
+        assert_eq!(
+            solve_line(
+                "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfe",
+                &digits
+            ),
+            Err(DecodeError::Inconsistent)
+        );
+
+        assert_eq!(
+            solve_line("ab | ax", &digits),
+            Err(DecodeError::Inconsistent)
+        );
+        // there is no wire h on a seven segment display
+        assert_eq!(
+            find_wiring(&masks(&["ah"]), 1, &digits),
+            Err(DecodeError::Inconsistent)
+        );
+    }
+
+    fn masks(patterns: &[&str]) -> Vec<u8> {
+        patterns.iter().map(|p| parse_pattern(p).unwrap()).collect()
+    }
+
+    // shows the symbols on a display where wire a is connected to segment d, b to e and so on
+    fn scrambled_entry(font: &Font, output: &str) -> String {
+        let scramble = |mask: u8| -> String {
+            (0..7)
+                .filter(|segment| mask & 1 << segment != 0)
+                .map(|segment| (b'a' + (segment + 4) % 7) as char)
+                .collect()
+        };
+        let patterns: Vec<String> = font.glyphs.iter().map(|&(_, s)| scramble(s)).collect();
+        let output: Vec<String> = output
+            .chars()
+            .map(|symbol| {
+                let (_, segments) = font.glyphs.iter().find(|(s, _)| *s == symbol).unwrap();
+                scramble(*segments)
+            })
+            .collect();
+        format!("{} | {}", patterns.join(" "), output.join(" "))
+    }
+
+    #[test]
+    fn other_fonts() {
+        let digits = Font::digits();
+        assert_eq!(digits.identifiable_by_count(), ['1', '4', '7', '8']);
+
+        let hex = Font::hex();
+        assert_eq!(hex.identifiable_by_count(), ['1', '7', '8']);
+        let entry = scrambled_entry(&hex, "CAFE");
+        assert_eq!(solve_line(&entry, &hex), Ok(0xCAFE));
+        assert_eq!(solve_line(&entry, &digits), Err(DecodeError::Inconsistent));
+
+        // a 7 with a tail and a 9 without one
+        let tails = Font::new(&[
+            ('0', "abcefg"),
+            ('1', "cf"),
+            ('2', "acdeg"),
+            ('3', "acdfg"),
+            ('4', "bcdf"),
+            ('5', "abdfg"),
+            ('6', "abdefg"),
+            ('7', "abcf"),
+            ('8', "abcdefg"),
+            ('9', "abcdf"),
+        ]);
+        assert_eq!(tails.identifiable_by_count(), ['1', '8']);
+        let entry = scrambled_entry(&tails, "7913");
+        assert_eq!(solve_line(&entry, &tails), Ok(7913));
+        assert_eq!(puzzle1(&entry, &tails), 1);
+    }
+
+    // `1000 * repeat` entries with shuffled wires and the sum of their outputs
+    fn stress_log(digits: &Font, repeat: usize) -> (String, u64) {
+        let segments = ["abcdefg", "bcdefga", "gfedcba", "dgbface", "cagedbf"];
+
+        // shuffle the wires of each entry around with a small LCG
+        let mut rng = Lcg::new(2021);
+        let mut entries = String::new();
+        let mut expected_sum = 0;
+        for _ in 0..1000 {
+            let wires = segments[rng.below(segments.len() as u64) as usize].as_bytes();
+            let value = rng.below(10000);
+
+            let show = |digit: usize| -> String {
+                let (_, mask) = digits.glyphs[digit];
+                (0..7)
+                    .filter(|s| mask & 1 << s != 0)
+                    .map(|s| wires[s] as char)
+                    .collect()
+            };
+            let patterns: Vec<String> = (0..10).map(show).collect();
+            let output: Vec<String> = format!("{:04}", value)
+                .bytes()
+                .map(|d| show((d - b'0') as usize))
+                .collect();
+            entries.push_str(&patterns.join(" "));
+            entries.push_str(" | ");
+            entries.push_str(&output.join(" "));
+            entries.push('\n');
+            expected_sum += value;
+        }
+
+        (entries.repeat(repeat), expected_sum * repeat as u64)
+    }
+
+    #[test]
+    fn stress_test() {
+        let digits = Font::digits();
+        let (log, expected_sum) = stress_log(&digits, 100);
+        assert_eq!(log.lines().count(), 100_000);
+        assert_eq!(puzzle2(&log, &digits), Ok(expected_sum));
+    }
+
+    // a million lines take about a second in a release build, so this one only runs on request:
+    // cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn million_lines() {
+        let digits = Font::digits();
+        let (log, expected_sum) = stress_log(&digits, 1000);
+        assert_eq!(log.lines().count(), 1_000_000);
+        assert_eq!(puzzle2(&log, &digits), Ok(expected_sum));
     }
 }
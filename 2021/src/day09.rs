@@ -1,10 +1,12 @@
+use crate::options;
+
 const INPUT: &str = include_str!("../inputs/day09");
 
 struct Location {
     height: i32,
-    visited: bool,
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 struct Position {
     x: usize,
     y: usize,
@@ -17,92 +19,238 @@ fn read_input(input: &str) -> Vec<Vec<Location>> {
             l.chars()
                 .map(|c| Location {
                     height: c.to_digit(10).unwrap() as i32,
-                    visited: false,
                 })
                 .collect()
         })
         .collect()
 }
 
-fn find_low_points(heightmap: &[Vec<Location>]) -> Vec<Position> {
-    let mut low_points: Vec<Position> = Vec::new();
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Neighbourhood {
+    Four,
+    Eight,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Plateaus {
+    // only single locations that are lower than all of their neighbours
+    Strict,
+    // a connected flat region that is lower than everything around it is one low region
+    Regions,
+}
+
+// a low point, or a flat region of equal height when plateaus count
+#[derive(Debug, PartialEq)]
+struct LowRegion {
+    height: i32,
+    positions: Vec<Position>,
+}
+
+fn neighbours(
+    pos: Position,
+    map_width: usize,
+    map_height: usize,
+    neighbourhood: Neighbourhood,
+) -> Vec<Position> {
+    let mut neighbours = Vec::with_capacity(8);
+    for dy in -1..=1_isize {
+        for dx in -1..=1_isize {
+            let diagonal = dx != 0 && dy != 0;
+            if (dx == 0 && dy == 0) || (diagonal && neighbourhood == Neighbourhood::Four) {
+                continue;
+            }
+            let (x, y) = (pos.x as isize + dx, pos.y as isize + dy);
+            if x >= 0 && y >= 0 && (x as usize) < map_width && (y as usize) < map_height {
+                neighbours.push(Position {
+                    x: x as usize,
+                    y: y as usize,
+                });
+            }
+        }
+    }
+    neighbours
+}
 
+// connected regions of equal height, in reading order of their first location
+fn flat_regions(heightmap: &[Vec<Location>], neighbourhood: Neighbourhood) -> Vec<Vec<Position>> {
     let map_width = heightmap[0].len();
     let map_height = heightmap.len();
+    let height = |pos: Position| heightmap[pos.y][pos.x].height;
+
+    let mut regions = Vec::new();
+    let mut seen = vec![vec![false; map_width]; map_height];
 
     for y in 0..map_height {
         for x in 0..map_width {
-            let height = heightmap[y][x].height;
-            let is_low_point = (x == 0 || height < heightmap[y][x - 1].height)
-                && (y == 0 || height < heightmap[y - 1][x].height)
-                && (x == (map_width - 1) || height < heightmap[y][x + 1].height)
-                && (y == (map_height - 1) || height < heightmap[y + 1][x].height);
-
-            if is_low_point {
-                low_points.push(Position { x, y });
+            if seen[y][x] {
+                continue;
+            }
+            seen[y][x] = true;
+
+            let region_height = heightmap[y][x].height;
+            let mut positions = vec![Position { x, y }];
+            let mut i = 0;
+            while i < positions.len() {
+                for n in neighbours(positions[i], map_width, map_height, neighbourhood) {
+                    if height(n) == region_height && !seen[n.y][n.x] {
+                        seen[n.y][n.x] = true;
+                        positions.push(n);
+                    }
+                }
+                i += 1;
             }
+            regions.push(positions);
         }
     }
 
-    low_points
+    regions
 }
 
-fn puzzle1(input: &str) -> i32 {
-    let heightmap = read_input(&input);
-    let low_points = find_low_points(&heightmap);
-    low_points
+// keeps the flat regions without anything lower around.
+// a strict low point is exactly such a region that consists of a single location.
+fn find_low_regions(
+    heightmap: &[Vec<Location>],
+    neighbourhood: Neighbourhood,
+    plateaus: Plateaus,
+) -> Vec<LowRegion> {
+    let map_width = heightmap[0].len();
+    let map_height = heightmap.len();
+    let height = |pos: Position| heightmap[pos.y][pos.x].height;
+
+    flat_regions(heightmap, neighbourhood)
+        .into_iter()
+        .filter_map(|positions| {
+            let region_height = height(positions[0]);
+            let is_low = positions.iter().all(|&pos| {
+                neighbours(pos, map_width, map_height, neighbourhood)
+                    .iter()
+                    .all(|&n| height(n) >= region_height)
+            });
+
+            (is_low && (plateaus == Plateaus::Regions || positions.len() == 1)).then_some(
+                LowRegion {
+                    height: region_height,
+                    positions,
+                },
+            )
+        })
+        .collect()
+}
+
+// every low region counts once, no matter how many locations it covers
+fn risk_level_sum(
+    heightmap: &[Vec<Location>],
+    neighbourhood: Neighbourhood,
+    plateaus: Plateaus,
+) -> i32 {
+    find_low_regions(heightmap, neighbourhood, plateaus)
         .iter()
-        .map(|pos| heightmap[pos.y][pos.x].height + 1)
+        .map(|region| region.height + 1)
         .sum()
 }
 
-fn visit_location(heightmap: &mut [Vec<Location>], pos: Position) -> usize {
-    let mut locations_visited = 1;
-    let mut adjacent_cells: Vec<Position> = Vec::new();
-    heightmap[pos.y][pos.x].visited = true;
+fn puzzle1(input: &str) -> i32 {
+    let heightmap = read_input(input);
+    risk_level_sum(&heightmap, Neighbourhood::Four, Plateaus::Strict)
+}
 
-    if pos.x > 0 {
-        adjacent_cells.push(Position {
-            x: pos.x - 1,
-            y: pos.y,
-        });
-    }
-    if pos.x < heightmap[0].len() - 1 {
-        adjacent_cells.push(Position {
-            x: pos.x + 1,
-            y: pos.y,
-        });
-    }
-    if pos.y > 0 {
-        adjacent_cells.push(Position {
-            x: pos.x,
-            y: pos.y - 1,
-        });
-    }
-    if pos.y < heightmap.len() - 1 {
-        adjacent_cells.push(Position {
-            x: pos.x,
-            y: pos.y + 1,
-        });
-    }
+#[derive(Debug, PartialEq)]
+struct Basin {
+    low_point: Position,
+    size: usize,
+    // bounding box, both corners inclusive
+    top_left: Position,
+    bottom_right: Position,
+}
+
+struct BasinMap {
+    // index into `basins` for every location, None for the 9s in between
+    labels: Vec<Vec<Option<usize>>>,
+    basins: Vec<Basin>,
+}
+
+// every location belongs to the basin its smoke flows into, i.e. it follows the steepest way down.
+// a flat region drains as a whole into the lowest location around it, or is the bottom of its own
+// basin if there is none. regions are labelled from low to high, so the lower neighbour is always
+// labelled already and basins that touch without a 9 in between are still split where the flow
+// splits.
+fn label_basins(heightmap: &[Vec<Location>]) -> BasinMap {
+    let map_width = heightmap[0].len();
+    let map_height = heightmap.len();
+    let height = |pos: Position| heightmap[pos.y][pos.x].height;
+
+    let mut regions: Vec<Vec<Position>> = flat_regions(heightmap, Neighbourhood::Four)
+        .into_iter()
+        .filter(|region| height(region[0]) != 9)
+        .collect();
+    regions.sort_by_key(|region| height(region[0]));
+
+    let mut labels = vec![vec![None; map_width]; map_height];
+    let mut basins: Vec<Basin> = Vec::new();
+
+    for region in regions {
+        let lowest = region
+            .iter()
+            .flat_map(|&pos| neighbours(pos, map_width, map_height, Neighbourhood::Four))
+            .filter(|&n| height(n) < height(region[0]))
+            .min_by_key(|&n| height(n));
 
-    for cell in adjacent_cells {
-        if heightmap[cell.y][cell.x].height != 9 && !heightmap[cell.y][cell.x].visited {
-            locations_visited += visit_location(heightmap, cell);
+        let label = match lowest {
+            Some(lowest) => labels[lowest.y][lowest.x].unwrap(),
+            None => {
+                basins.push(Basin {
+                    low_point: region[0],
+                    size: 0,
+                    top_left: region[0],
+                    bottom_right: region[0],
+                });
+                basins.len() - 1
+            }
+        };
+
+        let basin = &mut basins[label];
+        for pos in region {
+            basin.size += 1;
+            basin.top_left.x = basin.top_left.x.min(pos.x);
+            basin.top_left.y = basin.top_left.y.min(pos.y);
+            basin.bottom_right.x = basin.bottom_right.x.max(pos.x);
+            basin.bottom_right.y = basin.bottom_right.y.max(pos.y);
+            labels[pos.y][pos.x] = Some(label);
         }
     }
 
-    return locations_visited;
+    BasinMap { labels, basins }
 }
 
-fn puzzle2(input: &str) -> usize {
-    let mut heightmap = read_input(&input);
-    let low_points = find_low_points(&heightmap);
-    let mut basin_sizes: Vec<usize> = Vec::new();
+// every basin gets its own background colour, the 9s stay uncoloured
+fn render_basins(heightmap: &[Vec<Location>], basin_map: &BasinMap) -> String {
+    // the 256 colour palette minus the darkest and brightest ones
+    const COLOURS: [u8; 12] = [1, 2, 3, 4, 5, 6, 9, 10, 11, 12, 13, 14];
 
-    for low_point in low_points {
-        basin_sizes.push(visit_location(&mut heightmap, low_point));
+    let mut output = String::new();
+    for (row, labels) in heightmap.iter().zip(&basin_map.labels) {
+        for (location, label) in row.iter().zip(labels) {
+            match label {
+                Some(label) => output.push_str(&format!(
+                    "\x1b[48;5;{}m{}\x1b[0m",
+                    COLOURS[label % COLOURS.len()],
+                    location.height
+                )),
+                None => output.push_str(&location.height.to_string()),
+            }
+        }
+        output.push('\n');
     }
+    output
+}
+
+fn puzzle2(input: &str) -> usize {
+    let heightmap = read_input(input);
+    let mut basin_sizes: Vec<usize> = label_basins(&heightmap)
+        .basins
+        .iter()
+        .map(|basin| basin.size)
+        .collect();
 
     basin_sizes.sort();
     basin_sizes.iter().rev().take(3).product()
@@ -112,15 +260,31 @@ pub fn day09() {
     println!("\nDay 9:");
 
     println!("Puzzle 1:");
-    let risk_level = puzzle1(&INPUT);
+    let risk_level = puzzle1(INPUT);
     println!("The sum of all risk levels is {}", risk_level);
 
     println!("Puzzle 2:");
-    let result = puzzle2(&INPUT);
+    let result = puzzle2(INPUT);
     println!(
         "The product of the sizes of the three largest basins is {}",
         result
     );
+
+    let heightmap = read_input(INPUT);
+    for neighbourhood in [Neighbourhood::Four, Neighbourhood::Eight] {
+        for plateaus in [Plateaus::Strict, Plateaus::Regions] {
+            println!(
+                "With {:?} neighbours and {:?} plateaus, the risk level sum is {}",
+                neighbourhood,
+                plateaus,
+                risk_level_sum(&heightmap, neighbourhood, plateaus)
+            );
+        }
+    }
+
+    if options::get().render {
+        print!("{}", render_basins(&heightmap, &label_basins(&heightmap)));
+    }
 }
 
 #[cfg(test)]
@@ -135,13 +299,169 @@ mod tests {
 
     #[test]
     fn puzzle1_example() {
-        let risk_level = puzzle1(&TEST_INPUT);
+        let risk_level = puzzle1(TEST_INPUT);
         assert_eq!(risk_level, 15);
     }
 
     #[test]
     fn puzzle2_example() {
-        let product = puzzle2(&TEST_INPUT);
+        let product = puzzle2(TEST_INPUT);
         assert_eq!(product, 1134);
     }
+
+    #[test]
+    fn low_regions_test() {
+        let heightmap = read_input(TEST_INPUT);
+        for neighbourhood in [Neighbourhood::Four, Neighbourhood::Eight] {
+            for plateaus in [Plateaus::Strict, Plateaus::Regions] {
+                assert_eq!(risk_level_sum(&heightmap, neighbourhood, plateaus), 15);
+            }
+        }
+
+        // the two 1s only count as a flat region
+        let heightmap = read_input("5555\n5115\n5525\n3555");
+        assert_eq!(
+            find_low_regions(&heightmap, Neighbourhood::Four, Plateaus::Strict),
+            vec![LowRegion {
+                height: 3,
+                positions: vec![Position { x: 0, y: 3 }]
+            }]
+        );
+        assert_eq!(
+            find_low_regions(&heightmap, Neighbourhood::Four, Plateaus::Regions),
+            vec![
+                LowRegion {
+                    height: 1,
+                    positions: vec![Position { x: 1, y: 1 }, Position { x: 2, y: 1 }]
+                },
+                LowRegion {
+                    height: 3,
+                    positions: vec![Position { x: 0, y: 3 }]
+                },
+            ]
+        );
+        assert_eq!(
+            risk_level_sum(&heightmap, Neighbourhood::Eight, Plateaus::Regions),
+            6
+        );
+
+        // diagonally, the 3 is next to the 2
+        let heightmap = read_input("9299\n9939");
+        assert_eq!(
+            risk_level_sum(&heightmap, Neighbourhood::Four, Plateaus::Strict),
+            7
+        );
+        assert_eq!(
+            risk_level_sum(&heightmap, Neighbourhood::Eight, Plateaus::Strict),
+            3
+        );
+
+        // four strict low points, or a single diagonally connected region
+        let heightmap = read_input("919\n191\n919");
+        assert_eq!(
+            risk_level_sum(&heightmap, Neighbourhood::Four, Plateaus::Strict),
+            8
+        );
+        assert_eq!(
+            risk_level_sum(&heightmap, Neighbourhood::Eight, Plateaus::Strict),
+            0
+        );
+        assert_eq!(
+            risk_level_sum(&heightmap, Neighbourhood::Eight, Plateaus::Regions),
+            2
+        );
+    }
+
+    #[test]
+    fn label_basins_test() {
+        let heightmap = read_input(TEST_INPUT);
+        let basin_map = label_basins(&heightmap);
+
+        let mut low_points: Vec<Position> = basin_map
+            .basins
+            .iter()
+            .map(|basin| basin.low_point)
+            .collect();
+        low_points.sort_by_key(|pos| (pos.y, pos.x));
+        let strict_low_points: Vec<Position> =
+            find_low_regions(&heightmap, Neighbourhood::Four, Plateaus::Strict)
+                .iter()
+                .map(|region| region.positions[0])
+                .collect();
+        assert_eq!(low_points, strict_low_points);
+
+        let top_right = basin_map.labels[0][9].unwrap();
+        assert_eq!(
+            basin_map.basins[top_right],
+            Basin {
+                low_point: Position { x: 9, y: 0 },
+                size: 9,
+                top_left: Position { x: 5, y: 0 },
+                bottom_right: Position { x: 9, y: 2 },
+            }
+        );
+        assert_eq!(basin_map.labels[0][4], None);
+        assert_eq!(basin_map.labels[1][8], Some(top_right));
+    }
+
+    #[test]
+    fn touching_basins() {
+        let heightmap = read_input("12341\n23452");
+        let basin_map = label_basins(&heightmap);
+        assert_eq!(basin_map.basins.len(), 2);
+
+        let sizes: Vec<usize> = basin_map.basins.iter().map(|b| b.size).collect();
+        assert_eq!(sizes, [6, 4]);
+        assert_eq!(
+            basin_map.labels,
+            vec![
+                vec![Some(0), Some(0), Some(0), Some(1), Some(1)],
+                vec![Some(0), Some(0), Some(0), Some(1), Some(1)],
+            ]
+        );
+    }
+
+    #[test]
+    fn flat_basins() {
+        // the whole row drains into the 1
+        let basin_map = label_basins(&read_input("221"));
+        assert_eq!(
+            basin_map.basins,
+            vec![Basin {
+                low_point: Position { x: 2, y: 0 },
+                size: 3,
+                top_left: Position { x: 0, y: 0 },
+                bottom_right: Position { x: 2, y: 0 },
+            }]
+        );
+
+        // a flat region around a 9 is still one basin
+        let basin_map = label_basins(&read_input("191\n111"));
+        assert_eq!(basin_map.basins.len(), 1);
+        assert_eq!(basin_map.basins[0].size, 5);
+        assert_eq!(basin_map.basins[0].low_point, Position { x: 0, y: 0 });
+        assert_eq!(basin_map.labels[0][1], None);
+
+        // the plateau drains into the lower of the two basins next to it
+        let basin_map = label_basins(&read_input("13330"));
+        assert_eq!(basin_map.basins.len(), 2);
+        assert_eq!(
+            basin_map.labels,
+            vec![vec![Some(1), Some(0), Some(0), Some(0), Some(0)]]
+        );
+    }
+
+    #[test]
+    fn large_basin() {
+        // a single basin this size would overflow the stack when flooded recursively
+        let row = "1".repeat(500) + "\n";
+        let input = "0".to_string() + &row[1..] + &row.repeat(499);
+        let basin_map = label_basins(&read_input(&input));
+        assert_eq!(basin_map.basins.len(), 1);
+        assert_eq!(basin_map.basins[0].size, 250_000);
+        assert_eq!(
+            basin_map.basins[0].bottom_right,
+            Position { x: 499, y: 499 }
+        );
+    }
 }
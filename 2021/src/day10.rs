@@ -1,3 +1,5 @@
+use std::io::{self, BufRead};
+
 const INPUT: &str = include_str!("../inputs/day10");
 
 enum LineValidity {
@@ -6,86 +8,298 @@ enum LineValidity {
     SyntaxError(char),
 }
 
-fn check_line(line: &str) -> LineValidity {
+struct Delimiters {
+    // (opener, closer)
+    pairs: Vec<(char, char)>,
+    // characters that are neither an opener nor a closer are skipped instead of being an error
+    ignore_other: bool,
+}
+
+impl Delimiters {
+    // the chunks of the navigation subsystem, which consist of nothing else
+    fn chunks() -> Delimiters {
+        Delimiters {
+            pairs: vec![('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')],
+            ignore_other: false,
+        }
+    }
+
+    fn closer_for(&self, c: char) -> Option<char> {
+        self.pairs
+            .iter()
+            .find(|&&(opener, _)| opener == c)
+            .map(|&(_, closer)| closer)
+    }
+
+    fn opener_for(&self, c: char) -> Option<char> {
+        self.pairs
+            .iter()
+            .find(|&&(_, closer)| closer == c)
+            .map(|&(opener, _)| opener)
+    }
+
+    fn is_closer(&self, c: char) -> bool {
+        self.opener_for(c).is_some()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum DelimiterError {
+    // `expected` is None if nothing was open
+    Mismatched { expected: Option<char>, found: char },
+    UnexpectedCharacter(char),
+}
+
+// columns start at 1
+#[derive(Debug, PartialEq)]
+struct Validation {
+    errors: Vec<(usize, DelimiterError)>,
+    // closers for everything that is still open, innermost first
+    missing: Vec<char>,
+}
+
+// keeps going after an error: a wrong closer is treated as if it was the expected one,
+// and a closer without anything open is skipped
+fn validate(line: &str, delimiters: &Delimiters) -> Validation {
     let mut stack: Vec<char> = Vec::new();
-    for c in line.chars() {
-        match c {
-            '(' => stack.push(')'),
-            '[' => stack.push(']'),
-            '{' => stack.push('}'),
-            '<' => stack.push('>'),
-            ')' | ']' | '}' | '>' => {
-                if stack.pop().unwrap_or('!') != c {
-                    return LineValidity::SyntaxError(c);
-                }
+    let mut errors = Vec::new();
+
+    for (i, c) in line.chars().enumerate() {
+        let column = i + 1;
+        if let Some(closer) = delimiters.closer_for(c) {
+            stack.push(closer);
+        } else if delimiters.is_closer(c) {
+            let expected = stack.pop();
+            if expected != Some(c) {
+                errors.push((column, DelimiterError::Mismatched { expected, found: c }));
             }
-            _ => panic!("unexpected character"),
+        } else if !delimiters.ignore_other {
+            errors.push((column, DelimiterError::UnexpectedCharacter(c)));
         }
     }
 
-    if !stack.is_empty() {
-        stack.reverse();
-        return LineValidity::Incomplete(stack);
+    stack.reverse();
+    Validation {
+        errors,
+        missing: stack,
+    }
+}
+
+fn check_line(line: &str) -> LineValidity {
+    let validation = validate(line, &Delimiters::chunks());
+
+    match validation.errors.first() {
+        Some((_, DelimiterError::Mismatched { found, .. })) => LineValidity::SyntaxError(*found),
+        Some((_, DelimiterError::UnexpectedCharacter(_))) => panic!("unexpected character"),
+        None if !validation.missing.is_empty() => LineValidity::Incomplete(validation.missing),
+        None => LineValidity::Valid,
     }
+}
+
+// columns refer to the line as it is when the edit is made, i.e. after all previous edits
+#[derive(Debug, PartialEq)]
+enum Edit {
+    Replace { column: usize, with: char },
+    // the opener ends up at `column`
+    Insert { column: usize, opener: char },
+    Remove { column: usize },
+    Append(String),
+}
 
-    LineValidity::Valid
+#[derive(Debug, PartialEq)]
+struct Repair {
+    line: String,
+    edits: Vec<Edit>,
 }
 
-fn puzzle1(input: &str) -> i32 {
-    let mut score = 0;
-    for line in input.lines() {
-        match check_line(line) {
-            LineValidity::SyntaxError(c) => {
-                score += match c {
-                    ')' => 3,
-                    ']' => 57,
-                    '}' => 1197,
-                    '>' => 25137,
-                    _ => panic!("unexpected character"),
+// column of the innermost chunk that is still open at the end of `line`
+fn innermost_open_column(line: &[char], delimiters: &Delimiters) -> Option<usize> {
+    let mut open = Vec::new();
+    for (i, &c) in line.iter().enumerate() {
+        if delimiters.closer_for(c).is_some() {
+            open.push(i + 1);
+        } else if delimiters.is_closer(c) {
+            open.pop();
+        }
+    }
+    open.pop()
+}
+
+fn apply(line: &mut Vec<char>, edit: &Edit) {
+    match *edit {
+        Edit::Replace { column, with } => line[column - 1] = with,
+        Edit::Insert { column, opener } => line.insert(column - 1, opener),
+        Edit::Remove { column } => {
+            line.remove(column - 1);
+        }
+        Edit::Append(ref completion) => line.extend(completion.chars()),
+    }
+}
+
+fn error_count(line: &[char], delimiters: &Delimiters) -> usize {
+    validate(&line.iter().collect::<String>(), delimiters)
+        .errors
+        .len()
+}
+
+// fixes one error after the other, always picking the single edit that leaves the fewest errors
+// behind, and finally appends whatever is missing
+fn repair(line: &str, delimiters: &Delimiters) -> Repair {
+    let mut repaired: Vec<char> = line.chars().collect();
+    let mut edits = Vec::new();
+
+    loop {
+        let validation = validate(&repaired.iter().collect::<String>(), delimiters);
+        let Some((column, error)) = validation.errors.into_iter().next() else {
+            if !validation.missing.is_empty() {
+                let completion = Edit::Append(validation.missing.into_iter().collect());
+                apply(&mut repaired, &completion);
+                edits.push(completion);
+            }
+            break;
+        };
+
+        let edit = match error {
+            DelimiterError::UnexpectedCharacter(_) => Edit::Remove { column },
+            DelimiterError::Mismatched {
+                expected: None,
+                found,
+            } => Edit::Insert {
+                column: 1,
+                opener: delimiters.opener_for(found).unwrap(),
+            },
+            DelimiterError::Mismatched {
+                expected: Some(expected),
+                found,
+            } => {
+                let replace = Edit::Replace {
+                    column,
+                    with: expected,
+                };
+                // wrap everything since the innermost open chunk in a new one
+                let insert = Edit::Insert {
+                    column: innermost_open_column(&repaired[..column - 1], delimiters).unwrap() + 1,
+                    opener: delimiters.opener_for(found).unwrap(),
+                };
+
+                let errors_after = |edit: &Edit| {
+                    let mut candidate = repaired.clone();
+                    apply(&mut candidate, edit);
+                    error_count(&candidate, delimiters)
+                };
+                if errors_after(&insert) < errors_after(&replace) {
+                    insert
+                } else {
+                    replace
                 }
             }
-            _ => {}
-        }
+        };
+
+        apply(&mut repaired, &edit);
+        edits.push(edit);
     }
 
-    score
+    Repair {
+        line: repaired.into_iter().collect(),
+        edits,
+    }
 }
 
-fn puzzle2(input: &str) -> i64 {
-    let mut scores: Vec<i64> = input
-        .lines()
-        .filter_map(|line| match check_line(line) {
+fn syntax_error_score(c: char) -> i64 {
+    match c {
+        ')' => 3,
+        ']' => 57,
+        '}' => 1197,
+        '>' => 25137,
+        _ => panic!("unexpected character"),
+    }
+}
+
+// None if the score doesn't fit, which happens from 28 missing characters on
+fn completion_score(missing_chars: &[char]) -> Option<i64> {
+    missing_chars.iter().try_fold(0i64, |score, c| {
+        let points = match c {
+            ')' => 1,
+            ']' => 2,
+            '}' => 3,
+            '>' => 4,
+            _ => panic!("unexpected character"),
+        };
+        score.checked_mul(5)?.checked_add(points)
+    })
+}
+
+// the lower one of the two middle values for even lengths, in O(n) on average
+fn median(scores: &mut [i64]) -> Option<i64> {
+    if scores.is_empty() {
+        return None;
+    }
+    let (_, median, _) = scores.select_nth_unstable((scores.len() - 1) / 2);
+    Some(*median)
+}
+
+#[derive(Debug, PartialEq)]
+struct Scores {
+    syntax_error: i64,
+    // None if no line is incomplete
+    middle_completion: Option<i64>,
+}
+
+// scores one line at a time, so only the completion scores are kept around
+fn score_stream<R: BufRead>(reader: R) -> io::Result<Scores> {
+    let mut syntax_error = 0;
+    let mut completion_scores = Vec::new();
+
+    for (number, line) in reader.lines().enumerate() {
+        match check_line(&line?) {
+            LineValidity::SyntaxError(c) => syntax_error += syntax_error_score(c),
             LineValidity::Incomplete(missing_chars) => {
-                let score = missing_chars.iter().fold(0, |score, c| {
-                    score * 5
-                        + match c {
-                            ')' => 1,
-                            ']' => 2,
-                            '}' => 3,
-                            '>' => 4,
-                            _ => panic!("unexpected character"),
-                        }
-                });
-                Some(score)
+                let score = completion_score(&missing_chars).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("completion score of line {} overflows", number + 1),
+                    )
+                })?;
+                completion_scores.push(score)
             }
-            _ => None,
-        })
-        .collect();
+            LineValidity::Valid => {}
+        }
+    }
+
+    Ok(Scores {
+        syntax_error,
+        middle_completion: median(&mut completion_scores),
+    })
+}
 
-    scores.sort();
-    scores[(scores.len() - 1) / 2]
+fn puzzle1(input: &str) -> i64 {
+    score_stream(input.as_bytes()).unwrap().syntax_error
+}
+
+fn puzzle2(input: &str) -> i64 {
+    score_stream(input.as_bytes())
+        .unwrap()
+        .middle_completion
+        .expect("no incomplete lines")
 }
 
 pub fn day10() {
     println!("\nDay 10:");
 
     println!("Puzzle 1:");
-    let syntax_error_score = puzzle1(&INPUT);
+    let syntax_error_score = puzzle1(INPUT);
     println!("The total syntax error score is {}", syntax_error_score);
 
     println!("Puzzle 2:");
-    let middle_score = puzzle2(&INPUT);
+    let middle_score = puzzle2(INPUT);
     println!("The middle score of incomplete lines is {}", middle_score);
+
+    let delimiters = Delimiters::chunks();
+    let edits: usize = INPUT
+        .lines()
+        .map(|line| repair(line, &delimiters).edits.len())
+        .sum();
+    println!("Repairing all lines takes {} edits", edits);
 }
 
 #[cfg(test)]
@@ -104,13 +318,225 @@ mod tests {
 
     #[test]
     fn puzzle1_example() {
-        let syntax_error_score = puzzle1(&TEST_INPUT);
+        let syntax_error_score = puzzle1(TEST_INPUT);
         assert_eq!(syntax_error_score, 26397);
     }
 
     #[test]
     fn puzzle2_example() {
-        let incomplete_score = puzzle2(&TEST_INPUT);
+        let incomplete_score = puzzle2(TEST_INPUT);
         assert_eq!(incomplete_score, 288957);
     }
+
+    #[test]
+    fn validate_test() {
+        let validation = validate("{([(<{}[<>[]}>{[]{[(<()>", &Delimiters::chunks());
+        assert_eq!(
+            validation.errors,
+            vec![(
+                13,
+                DelimiterError::Mismatched {
+                    expected: Some(']'),
+                    found: '}'
+                }
+            )]
+        );
+        assert_eq!(
+            validation.missing,
+            vec![')', ']', '}', '}', ')', ']', ')', '}']
+        );
+
+        assert_eq!(
+            validate("<a)]>", &Delimiters::chunks()).errors,
+            vec![
+                (2, DelimiterError::UnexpectedCharacter('a')),
+                (
+                    3,
+                    DelimiterError::Mismatched {
+                        expected: Some('>'),
+                        found: ')'
+                    }
+                ),
+                (
+                    4,
+                    DelimiterError::Mismatched {
+                        expected: None,
+                        found: ']'
+                    }
+                ),
+                (
+                    5,
+                    DelimiterError::Mismatched {
+                        expected: None,
+                        found: '>'
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_delimiters() {
+        let code = Delimiters {
+            pairs: vec![('(', ')'), ('[', ']'), ('{', '}')],
+            ignore_other: true,
+        };
+        let validation = validate("fn main() { let v = vec![1, (2]; <>", &code);
+        assert_eq!(
+            validation,
+            Validation {
+                errors: vec![(
+                    31,
+                    DelimiterError::Mismatched {
+                        expected: Some(')'),
+                        found: ']'
+                    }
+                )],
+                missing: vec![']', '}'],
+            }
+        );
+
+        let quotes = Delimiters {
+            pairs: vec![('«', '»'), ('„', '“')],
+            ignore_other: true,
+        };
+        assert_eq!(
+            validate("«„Hi“, sagte sie.»", &quotes),
+            Validation {
+                errors: vec![],
+                missing: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn complete_test() {
+        let repaired = repair("[({(<(())[]>[[{[]{<()<>>", &Delimiters::chunks());
+        assert_eq!(
+            repaired,
+            Repair {
+                line: "[({(<(())[]>[[{[]{<()<>>}}]])})]".to_string(),
+                edits: vec![Edit::Append("}}]])})]".to_string())],
+            }
+        );
+
+        assert_eq!(
+            repair("<>", &Delimiters::chunks()),
+            Repair {
+                line: "<>".to_string(),
+                edits: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn repair_test() {
+        let chunks = Delimiters::chunks();
+
+        // a wrong closer is replaced
+        assert_eq!(
+            repair("{()(]}", &chunks),
+            Repair {
+                line: "{()()}".to_string(),
+                edits: vec![Edit::Replace {
+                    column: 5,
+                    with: ')'
+                }],
+            }
+        );
+
+        // unless an opener was forgotten
+        assert_eq!(
+            repair("{()]}", &chunks),
+            Repair {
+                line: "{[()]}".to_string(),
+                edits: vec![Edit::Insert {
+                    column: 2,
+                    opener: '['
+                }],
+            }
+        );
+        assert_eq!(
+            repair("()>", &chunks),
+            Repair {
+                line: "<()>".to_string(),
+                edits: vec![Edit::Insert {
+                    column: 1,
+                    opener: '<'
+                }],
+            }
+        );
+
+        assert_eq!(
+            repair("[<a>(]", &chunks),
+            Repair {
+                line: "[<>()]".to_string(),
+                edits: vec![
+                    Edit::Remove { column: 3 },
+                    Edit::Replace {
+                        column: 5,
+                        with: ')'
+                    },
+                    Edit::Append("]".to_string()),
+                ],
+            }
+        );
+
+        for line in TEST_INPUT.lines().chain(INPUT.lines()) {
+            let repaired = repair(line, &chunks);
+            assert!(matches!(check_line(&repaired.line), LineValidity::Valid));
+        }
+    }
+
+    #[test]
+    fn median_test() {
+        assert_eq!(median(&mut []), None);
+        assert_eq!(median(&mut [7]), Some(7));
+        assert_eq!(median(&mut [5, 1, 3]), Some(3));
+        assert_eq!(median(&mut [4, 1, 3, 2]), Some(2));
+    }
+
+    #[test]
+    fn stream_test() {
+        let scores = score_stream(TEST_INPUT.as_bytes()).unwrap();
+        assert_eq!(
+            scores,
+            Scores {
+                syntax_error: 26397,
+                middle_completion: Some(288957)
+            }
+        );
+
+        // a tiny buffer makes sure that nothing relies on seeing the whole input at once
+        let input = (TEST_INPUT.to_string() + "\n").repeat(1001);
+        let reader = io::BufReader::with_capacity(16, input.as_bytes());
+        let scores = score_stream(reader).unwrap();
+        assert_eq!(
+            scores,
+            Scores {
+                syntax_error: 26397 * 1001,
+                middle_completion: Some(288957)
+            }
+        );
+
+        assert_eq!(
+            score_stream("()\n(]".as_bytes()).unwrap(),
+            Scores {
+                syntax_error: 57,
+                middle_completion: None
+            }
+        );
+    }
+
+    #[test]
+    fn completion_overflow() {
+        // 5^27 - 1 is the largest score that still fits
+        assert_eq!(completion_score(&['>'; 27]), Some(5i64.pow(27) - 1));
+        assert_eq!(completion_score(&[')'; 28]), None);
+
+        let input = "<<\n".to_string() + &"(".repeat(30);
+        let error = score_stream(input.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "completion score of line 2 overflows");
+    }
 }
@@ -1,4 +1,8 @@
-use std::cmp::{max, min};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::animation::{self, Frame};
+use crate::options;
 
 const INPUT: &str = include_str!("../inputs/day11");
 
@@ -24,49 +28,89 @@ impl Octopus {
 }
 
 fn load_octopuses(input: &str) -> Vec<Vec<Octopus>> {
-    input
+    let octopuses: Vec<Vec<Octopus>> = input
         .lines()
         .map(|line| {
             line.chars()
                 .map(|c| Octopus::with_level(c.to_digit(10).unwrap()))
                 .collect()
         })
-        .collect()
-}
+        .collect();
 
-fn step(octopuses: &mut Vec<Vec<Octopus>>) -> usize {
-    let rows = octopuses.len();
-    let cols = octopuses[0].len();
+    assert!(
+        octopuses.iter().all(|row| row.len() == octopuses[0].len()),
+        "all rows need to have the same number of octopuses"
+    );
 
-    for octopus in octopuses.iter_mut().flatten() {
-        octopus.level += 1;
-    }
+    octopuses
+}
 
-    let mut total_flashes = 0;
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Boundary {
+    Bounded,
+    // octopuses on one edge are neighbours of those on the opposite edge
+    Toroidal,
+}
 
-    loop {
-        let mut new_flashing = 0;
-        for y in 0..rows {
-            for x in 0..cols {
-                if octopuses[y][x].flashing || octopuses[y][x].level <= 9 {
-                    continue;
+fn neighbours(
+    (y, x): (usize, usize),
+    rows: usize,
+    cols: usize,
+    boundary: Boundary,
+) -> Vec<(usize, usize)> {
+    let mut neighbours = Vec::with_capacity(8);
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let (ny, nx) = (y as isize + dy, x as isize + dx);
+            let neighbour = match boundary {
+                Boundary::Bounded => {
+                    if ny < 0 || nx < 0 || ny >= rows as isize || nx >= cols as isize {
+                        continue;
+                    }
+                    (ny as usize, nx as usize)
                 }
+                Boundary::Toroidal => (
+                    ny.rem_euclid(rows as isize) as usize,
+                    nx.rem_euclid(cols as isize) as usize,
+                ),
+            };
+            // on tiny tori the same octopus can be reached from several directions
+            if neighbour != (y, x) && !neighbours.contains(&neighbour) {
+                neighbours.push(neighbour);
+            }
+        }
+    }
+    neighbours
+}
 
-                new_flashing += 1;
-                octopuses[y][x].flashing = true;
+// every octopus is only visited when it flashes, so a step costs O(rows * cols + flashes)
+fn step(octopuses: &mut [Vec<Octopus>], boundary: Boundary) -> usize {
+    let rows = octopuses.len();
+    let cols = octopuses[0].len();
 
-                for ya in max(y as i32 - 1, 0)..min(y as i32 + 2, rows as i32) {
-                    for xa in max(x as i32 - 1, 0)..min(x as i32 + 2, cols as i32) {
-                        octopuses[ya as usize][xa as usize].level += 1;
-                    }
-                }
+    let mut flashing = Vec::new();
+    for (y, row) in octopuses.iter_mut().enumerate() {
+        for (x, octopus) in row.iter_mut().enumerate() {
+            octopus.level += 1;
+            if octopus.level > 9 {
+                octopus.flashing = true;
+                flashing.push((y, x));
             }
         }
+    }
 
-        total_flashes += new_flashing;
+    let mut total_flashes = 0;
 
-        if new_flashing == 0 {
-            break;
+    while let Some(position) = flashing.pop() {
+        total_flashes += 1;
+
+        for (y, x) in neighbours(position, rows, cols, boundary) {
+            let neighbour = &mut octopuses[y][x];
+            neighbour.level += 1;
+            if !neighbour.flashing && neighbour.level > 9 {
+                neighbour.flashing = true;
+                flashing.push((y, x));
+            }
         }
     }
 
@@ -79,49 +123,190 @@ fn step(octopuses: &mut Vec<Vec<Octopus>>) -> usize {
     total_flashes
 }
 
-fn puzzle1(input: &str, steps: u32) -> usize {
+fn puzzle1(input: &str, steps: u32, boundary: Boundary) -> usize {
     // TIL: octopuses is actually correct, octopodes is less common and octopi is wrong
     let mut octopuses = load_octopuses(input);
 
     let mut total_flashes = 0;
 
     for _ in 0..steps {
-        total_flashes += step(&mut octopuses);
+        total_flashes += step(&mut octopuses, boundary);
     }
 
     total_flashes
 }
 
-fn puzzle2(input: &str) -> usize {
+// nothing guarantees that all octopuses ever flash at once, especially with wrap-around edges.
+// None if they don't before the grid repeats itself, or within `max_steps`.
+fn puzzle2(input: &str, boundary: Boundary, max_steps: usize) -> Option<usize> {
+    simulate(input, boundary, max_steps).first_full_flash()
+}
+
+// the state of an octopus grid repeats, i.e. the state after `offset + period` steps is the same
+// as after `offset` steps
+#[derive(Debug, PartialEq)]
+struct Cycle {
+    offset: usize,
+    period: usize,
+}
+
+struct History {
+    // energy levels after every step, starting with the initial ones
+    states: Vec<Vec<Vec<u32>>>,
+    // flashes[i] is the number of flashes during step i + 1
+    flashes: Vec<usize>,
+    cycle: Option<Cycle>,
+}
+
+impl History {
+    fn first_full_flash(&self) -> Option<usize> {
+        let num_octopuses = self.states[0].len() * self.states[0][0].len();
+        self.flashes
+            .iter()
+            .position(|&flashes| flashes == num_octopuses)
+            .map(|i| i + 1)
+    }
+
+    fn flashes_after(&self, steps: u64) -> Option<u128> {
+        let sum = |range: &[usize]| range.iter().map(|&f| f as u128).sum::<u128>();
+
+        if steps <= self.flashes.len() as u64 {
+            return Some(sum(&self.flashes[..steps as usize]));
+        }
+
+        let Cycle { offset, period } = *self.cycle.as_ref()?;
+        let cycle_flashes = &self.flashes[offset..offset + period];
+        let remaining = steps - offset as u64;
+        let full_cycles = (remaining / period as u64) as u128;
+        let rest = (remaining % period as u64) as usize;
+
+        Some(
+            sum(&self.flashes[..offset])
+                + full_cycles * sum(cycle_flashes)
+                + sum(&cycle_flashes[..rest]),
+        )
+    }
+}
+
+fn levels(octopuses: &[Vec<Octopus>]) -> Vec<Vec<u32>> {
+    octopuses
+        .iter()
+        .map(|row| row.iter().map(|octopus| octopus.level).collect())
+        .collect()
+}
+
+// steps until the grid gets into a state it has been in before, or until `max_steps`
+fn simulate(input: &str, boundary: Boundary, max_steps: usize) -> History {
     let mut octopuses = load_octopuses(input);
-    let num_octopuses = octopuses.len() * octopuses[0].len();
-
-    let mut steps = 0;
-    loop {
-        steps += 1;
-        let flashes = step(&mut octopuses);
-        if flashes == num_octopuses {
-            return steps;
+
+    let mut states = vec![levels(&octopuses)];
+    let mut seen = HashMap::from([(states[0].clone(), 0)]);
+    let mut flashes = Vec::new();
+    let mut cycle = None;
+
+    while flashes.len() < max_steps {
+        flashes.push(step(&mut octopuses, boundary));
+
+        let state = levels(&octopuses);
+        if let Some(&offset) = seen.get(&state) {
+            cycle = Some(Cycle {
+                offset,
+                period: flashes.len() - offset,
+            });
+            states.push(state);
+            break;
         }
+        seen.insert(state.clone(), flashes.len());
+        states.push(state);
+    }
+
+    History {
+        states,
+        flashes,
+        cycle,
     }
 }
 
+// octopuses that just flashed are shown bold
+fn render(levels: &[Vec<u32>]) -> String {
+    let mut output = String::new();
+    for row in levels {
+        for &level in row {
+            if level == 0 {
+                output.push_str("\x1b[1m0\x1b[0m");
+            } else {
+                output.push(char::from_digit(level, 10).unwrap());
+            }
+        }
+        output.push('\n');
+    }
+    output
+}
+
+// plain (ascii) portable graymap. octopuses that just flashed are drawn at full brightness,
+// all others as dark as their energy level is low.
+fn to_pgm(levels: &[Vec<u32>]) -> String {
+    let mut pgm = format!("P2\n{} {}\n9\n", levels[0].len(), levels.len());
+    for row in levels {
+        let row: Vec<String> = row
+            .iter()
+            .map(|&level| if level == 0 { 9 } else { level - 1 }.to_string())
+            .collect();
+        pgm.push_str(&row.join(" "));
+        pgm.push('\n');
+    }
+    pgm
+}
+
 pub fn day11() {
     println!("\nDay 11:");
 
     println!("Puzzle 1:");
-    let total_flashes = puzzle1(INPUT, 100);
+    let total_flashes = puzzle1(INPUT, 100, Boundary::Bounded);
     println!(
         "The total number of flashes after 100 steps is {}",
         total_flashes
     );
 
     println!("Puzzle 2:");
-    let steps = puzzle2(INPUT);
+    let steps = puzzle2(INPUT, Boundary::Bounded, 10_000).expect("the octopuses never sync");
     println!(
         "The first step during which all octopuses flash is {}",
         steps
     );
+
+    match puzzle2(INPUT, Boundary::Toroidal, 10_000) {
+        Some(steps) => println!(
+            "With wrap-around edges, all octopuses first flash during step {}",
+            steps
+        ),
+        None => println!("With wrap-around edges, the octopuses don't all flash at once"),
+    }
+
+    let history = simulate(INPUT, Boundary::Bounded, 10_000);
+    if let Some(Cycle { offset, period }) = history.cycle {
+        println!(
+            "The octopuses repeat every {} steps after step {}, so there are {} flashes after 10^15 steps",
+            period,
+            offset,
+            history.flashes_after(1_000_000_000_000_000).unwrap()
+        );
+    }
+
+    let options = options::get();
+    if options.animate {
+        animation::animate(
+            &history.states,
+            Duration::from_millis(100),
+            options.frames_dir("day11").as_deref(),
+            |i, state| Frame {
+                screen: format!("{}step {}\n", render(state), i),
+                file_name: format!("step_{:04}.pgm", i),
+                image: to_pgm(state),
+            },
+        )
+        .expect("could not animate the octopuses");
+    }
 }
 
 #[cfg(test)]
@@ -141,13 +326,104 @@ mod tests {
 
     #[test]
     fn puzzle1_example() {
-        let total_flashes = puzzle1(TEST_INPUT, 100);
+        let total_flashes = puzzle1(TEST_INPUT, 100, Boundary::Bounded);
         assert_eq!(total_flashes, 1656);
     }
 
     #[test]
     fn puzzle2_example() {
-        let steps = puzzle2(TEST_INPUT);
-        assert_eq!(steps, 195);
+        let steps = puzzle2(TEST_INPUT, Boundary::Bounded, 1000);
+        assert_eq!(steps, Some(195));
+        assert_eq!(puzzle2(TEST_INPUT, Boundary::Bounded, 194), None);
+
+        // these never all flash at once, which is certain as soon as the grid repeats
+        assert_eq!(puzzle2("18", Boundary::Toroidal, usize::MAX), None);
+        assert_eq!(puzzle2("1234", Boundary::Toroidal, usize::MAX), None);
+        assert_eq!(puzzle2("123\n456", Boundary::Toroidal, usize::MAX), Some(4));
+    }
+
+    #[test]
+    fn small_example() {
+        let mut octopuses = load_octopuses("11111\n19991\n19191\n19991\n11111");
+        assert_eq!(step(&mut octopuses, Boundary::Bounded), 9);
+        assert_eq!(
+            octopuses,
+            load_octopuses("34543\n40004\n50005\n40004\n34543")
+        );
+        assert_eq!(step(&mut octopuses, Boundary::Bounded), 0);
+        assert_eq!(
+            octopuses,
+            load_octopuses("45654\n51115\n61116\n51115\n45654")
+        );
+    }
+
+    #[test]
+    fn non_square_grid() {
+        let mut octopuses = load_octopuses("999\n000");
+        assert_eq!(step(&mut octopuses, Boundary::Bounded), 3);
+        assert_eq!(octopuses, load_octopuses("000\n343"));
+    }
+
+    #[test]
+    fn toroidal_grid() {
+        let mut octopuses = load_octopuses("9000\n0000\n0000");
+        assert_eq!(step(&mut octopuses, Boundary::Toroidal), 1);
+        assert_eq!(octopuses, load_octopuses("0212\n2212\n2212"));
+
+        let mut octopuses = load_octopuses("9000\n0000\n0000");
+        assert_eq!(step(&mut octopuses, Boundary::Bounded), 1);
+        assert_eq!(octopuses, load_octopuses("0211\n2211\n1111"));
+    }
+
+    #[test]
+    fn history_test() {
+        let history = simulate(TEST_INPUT, Boundary::Bounded, 1000);
+        assert_eq!(history.flashes[..2], [0, 35]);
+        assert_eq!(history.flashes_after(10), Some(204));
+        assert_eq!(history.flashes_after(100), Some(1656));
+
+        // from step 195 on, all of them flash together every 10 steps
+        assert_eq!(
+            history.cycle,
+            Some(Cycle {
+                offset: 195,
+                period: 10
+            })
+        );
+        assert_eq!(history.states.len(), 206);
+        assert_eq!(history.states[195], history.states[205]);
+
+        let synchronized = history.flashes_after(195).unwrap();
+        assert_eq!(
+            history.flashes_after(195 + 10 * 1_000_000_000_000),
+            Some(synchronized + 100 * 1_000_000_000_000)
+        );
+        assert_eq!(
+            history.flashes_after(198 + 10 * 1_000_000_000_000),
+            Some(synchronized + 100 * 1_000_000_000_000)
+        );
+
+        let history = simulate(TEST_INPUT, Boundary::Bounded, 100);
+        assert_eq!(history.cycle, None);
+        assert_eq!(history.flashes_after(100), Some(1656));
+        assert_eq!(history.flashes_after(101), None);
+    }
+
+    #[test]
+    fn frames_test() {
+        let history = simulate("11111\n19991\n19191\n19991\n11111", Boundary::Bounded, 1);
+        assert_eq!(
+            to_pgm(&history.states[1]),
+            "P2
+5 5
+9
+2 3 4 3 2
+3 9 9 9 3
+4 9 9 9 4
+3 9 9 9 3
+2 3 4 3 2
+"
+        );
+        assert_eq!(render(&[vec![1, 0]]), "1\x1b[1m0\x1b[0m\n");
     }
 }
@@ -67,7 +67,7 @@ pub fn day12() {
     println!("\nDay 12:");
 
     println!("Puzzle 1:");
-    let num_paths = puzzle1(&INPUT);
+    let num_paths = puzzle1(INPUT);
     println!("The number of paths is {}", num_paths);
 }
 
@@ -115,8 +115,8 @@ start-RW";
 
     #[test]
     fn puzzle1_example() {
-        assert_eq!(puzzle1(&TEST_INPUT1), 10);
-        assert_eq!(puzzle1(&TEST_INPUT2), 19);
-        assert_eq!(puzzle1(&TEST_INPUT3), 226);
+        assert_eq!(puzzle1(TEST_INPUT1), 10);
+        assert_eq!(puzzle1(TEST_INPUT2), 19);
+        assert_eq!(puzzle1(TEST_INPUT3), 226);
     }
 }
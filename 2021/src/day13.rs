@@ -1,15 +1,31 @@
 use std::cmp::max;
+use std::collections::HashSet;
+use std::fmt;
+use std::time::Duration;
+
+use crate::animation::{self, Frame};
+use crate::options;
 
 const INPUT: &str = include_str!("../inputs/day13");
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 enum Fold {
     Vertical(usize),
     Horizontal(usize),
 }
 
-fn read_input(input: &str) -> (Vec<Vec<bool>>, Vec<Fold>) {
+// only the dots are stored, the size of the paper is tracked separately so that folds
+// which leave empty rows or columns at the edge still render the same
+#[derive(Clone)]
+struct Paper {
+    dots: HashSet<(usize, usize)>,
+    width: usize,
+    height: usize,
+}
+
+fn read_input(input: &str) -> (Paper, Vec<Fold>) {
     let (dots_str, folds_str) = input.split_once("\n\n").unwrap();
-    let dots: Vec<(usize, usize)> = dots_str
+    let dots: HashSet<(usize, usize)> = dots_str
         .lines()
         .map(|l| {
             let mut i = l.split(',').map(|n| n.parse().unwrap());
@@ -34,83 +50,269 @@ fn read_input(input: &str) -> (Vec<Vec<bool>>, Vec<Fold>) {
         })
         .collect();
 
-    let (cols, rows) = dots.iter().fold((0, 0), |(max_x, max_y), (x, y)| {
+    let (width, height) = dots.iter().fold((0, 0), |(max_x, max_y), (x, y)| {
         (max(max_x, *x + 1), max(max_y, *y + 1))
     });
 
-    let mut paper = vec![vec![false; cols]; rows];
-
-    for (x, y) in dots {
-        paper[y][x] = true;
-    }
+    (
+        Paper {
+            dots,
+            width,
+            height,
+        },
+        folds,
+    )
+}
 
-    (paper, folds)
+#[derive(Debug, PartialEq)]
+enum FoldError {
+    OutOfRange { fold: Fold, size: usize },
+    DotOnFoldLine { fold: Fold, dot: (usize, usize) },
 }
 
-fn fold_vertically(paper: &mut Vec<Vec<bool>>, column: usize) {
-    let rightmost = paper[0].len() - 1;
-    for y in 0..paper.len() {
-        for x in 0..column {
-            paper[y][x] |= paper[y][rightmost - x];
+impl fmt::Display for FoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FoldError::OutOfRange { fold, size } => {
+                write!(
+                    f,
+                    "{:?} is outside of the paper, which is {} long",
+                    fold, size
+                )
+            }
+            FoldError::DotOnFoldLine { fold, dot } => {
+                write!(f, "{:?} goes through the dot at {:?}", fold, dot)
+            }
         }
-        paper[y].resize(column, false);
     }
 }
 
-fn fold_horizontally(paper: &mut Vec<Vec<bool>>, row: usize) {
-    let lowest = paper.len() - 1;
-    for y in 0..row {
-        for x in 0..paper[y].len() {
-            paper[y][x] |= paper[lowest - y][x];
-        }
+// the part beyond the fold line is mirrored onto the part before it. if the mirrored part is the
+// larger one, it sticks out past 0 and everything gets shifted so that the paper starts at 0 again.
+fn fold_coordinate(coordinate: usize, line: usize, size: usize) -> usize {
+    let shift = (size - 1 - line).saturating_sub(line);
+    if coordinate < line {
+        coordinate + shift
+    } else {
+        2 * line + shift - coordinate
     }
-    paper.resize(row, Vec::new());
 }
 
-fn dot_count(paper: &Vec<Vec<bool>>) -> usize {
-    paper
+fn fold(paper: &mut Paper, fold: Fold) -> Result<(), FoldError> {
+    let (line, size) = match fold {
+        Fold::Vertical(x) => (x, paper.width),
+        Fold::Horizontal(y) => (y, paper.height),
+    };
+    if line >= size {
+        return Err(FoldError::OutOfRange { fold, size });
+    }
+
+    let on_line = |&&(x, y): &&(usize, usize)| match fold {
+        Fold::Vertical(_) => x == line,
+        Fold::Horizontal(_) => y == line,
+    };
+    if let Some(&dot) = paper.dots.iter().filter(on_line).min() {
+        return Err(FoldError::DotOnFoldLine { fold, dot });
+    }
+
+    paper.dots = paper
+        .dots
         .iter()
-        .flat_map(|row| row.iter())
-        .filter(|&cell| *cell)
-        .count()
+        .map(|&(x, y)| match fold {
+            Fold::Vertical(_) => (fold_coordinate(x, line, size), y),
+            Fold::Horizontal(_) => (x, fold_coordinate(y, line, size)),
+        })
+        .collect();
+
+    let new_size = line.max(size - 1 - line);
+    match fold {
+        Fold::Vertical(_) => paper.width = new_size,
+        Fold::Horizontal(_) => paper.height = new_size,
+    }
+
+    Ok(())
+}
+
+fn dot_count(paper: &Paper) -> usize {
+    paper.dots.len()
 }
 
 fn puzzle1(input: &str) -> usize {
     let (mut paper, folds) = read_input(input);
-    let fold = folds.first().unwrap();
-    match *fold {
-        Fold::Vertical(x) => fold_vertically(&mut paper, x),
-        Fold::Horizontal(y) => fold_horizontally(&mut paper, y),
-    }
+    fold(&mut paper, folds[0]).unwrap_or_else(|e| panic!("{}", e));
 
     dot_count(&paper)
 }
 
-fn puzzle2(input: &str) {
+fn fold_all(input: &str) -> Result<Paper, FoldError> {
     let (mut paper, folds) = read_input(input);
-    for fold in folds {
-        match fold {
-            Fold::Vertical(x) => fold_vertically(&mut paper, x),
-            Fold::Horizontal(y) => fold_horizontally(&mut paper, y),
-        }
+    for f in folds {
+        fold(&mut paper, f)?;
+    }
+    Ok(paper)
+}
+
+// the paper before any fold, followed by the paper after each fold
+fn fold_frames(input: &str) -> Result<Vec<Paper>, FoldError> {
+    let (mut paper, folds) = read_input(input);
+    let mut frames = vec![paper.clone()];
+    for f in folds {
+        fold(&mut paper, f)?;
+        frames.push(paper.clone());
     }
+    Ok(frames)
+}
 
-    for y in 0..paper.len() {
-        for x in 0..paper[y].len() {
-            print!("{}", if paper[y][x] { '#' } else { '.' });
+fn render(paper: &Paper) -> String {
+    let mut output = String::new();
+    for y in 0..paper.height {
+        output.extend((0..paper.width).map(|x| {
+            if paper.dots.contains(&(x, y)) {
+                '#'
+            } else {
+                '.'
+            }
+        }));
+        output.push('\n');
+    }
+    output
+}
+
+// plain (ascii) portable bitmap, where 1 is a dot
+fn to_pbm(paper: &Paper) -> String {
+    let mut pbm = format!("P1\n{} {}\n", paper.width, paper.height);
+    for y in 0..paper.height {
+        let row: Vec<&str> = (0..paper.width)
+            .map(|x| {
+                if paper.dots.contains(&(x, y)) {
+                    "1"
+                } else {
+                    "0"
+                }
+            })
+            .collect();
+        pbm.push_str(&row.join(" "));
+        pbm.push('\n');
+    }
+    pbm
+}
+
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+
+// the block letters used by advent of code, each followed by an empty column
+const FONT: [(char, [&str; GLYPH_HEIGHT]); 18] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...", "#...", ".#.#", "..#.", "..#.", "..#."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+#[derive(Debug, PartialEq)]
+enum OcrError {
+    WrongHeight(usize),
+    UnknownGlyph { position: usize, glyph: String },
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OcrError::WrongHeight(height) => write!(
+                f,
+                "letters are {} dots high, but the paper is {}",
+                GLYPH_HEIGHT, height
+            ),
+            OcrError::UnknownGlyph { position, glyph } => {
+                write!(f, "unknown glyph at position {}:\n{}", position, glyph)
+            }
         }
-        print!("\n");
     }
 }
 
+fn recognize(paper: &Paper) -> Result<String, OcrError> {
+    if paper.height != GLYPH_HEIGHT {
+        return Err(OcrError::WrongHeight(paper.height));
+    }
+
+    let glyph_count = paper.width.div_ceil(GLYPH_WIDTH + 1);
+
+    (0..glyph_count)
+        .map(|position| {
+            let left = position * (GLYPH_WIDTH + 1);
+            let glyph: Vec<String> = (0..GLYPH_HEIGHT)
+                .map(|y| {
+                    (left..left + GLYPH_WIDTH)
+                        .map(|x| {
+                            if paper.dots.contains(&(x, y)) {
+                                '#'
+                            } else {
+                                '.'
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+
+            FONT.iter()
+                .find(|(_, rows)| rows.iter().eq(glyph.iter()))
+                .map(|&(letter, _)| letter)
+                .ok_or_else(|| OcrError::UnknownGlyph {
+                    position,
+                    glyph: glyph.join("\n"),
+                })
+        })
+        .collect()
+}
+
+fn puzzle2(input: &str) -> String {
+    let paper = fold_all(input).unwrap_or_else(|e| panic!("{}", e));
+    recognize(&paper).unwrap_or_else(|e| panic!("{}", e))
+}
+
 pub fn day13() {
     println!("Day 13");
 
-    let visible_dots = puzzle1(&INPUT);
+    let visible_dots = puzzle1(INPUT);
     println!("Visible dots after first fold: {}", visible_dots);
 
     println!("Paper after all folds:");
-    puzzle2(&INPUT);
+    print!("{}", render(&fold_all(INPUT).unwrap()));
+    println!("Code: {}", puzzle2(INPUT));
+
+    let options = options::get();
+    if options.animate {
+        let frames = fold_frames(INPUT).unwrap_or_else(|e| panic!("{}", e));
+        animation::animate(
+            &frames,
+            Duration::from_millis(500),
+            options.frames_dir("day13").as_deref(),
+            |i, paper| Frame {
+                screen: format!(
+                    "{}after {} of {} folds\n",
+                    render(paper),
+                    i,
+                    frames.len() - 1
+                ),
+                file_name: format!("fold_{:02}.pbm", i),
+                image: to_pbm(paper),
+            },
+        )
+        .expect("could not animate the folds");
+    }
 }
 
 #[cfg(test)]
@@ -141,12 +343,142 @@ fold along x=5";
 
     #[test]
     fn p1_test() {
-        assert_eq!(puzzle1(&TEST_INPUT), 17);
+        assert_eq!(puzzle1(TEST_INPUT), 17);
 
         let (mut paper, _) = read_input(TEST_INPUT);
-        fold_horizontally(&mut paper, 7);
+        fold(&mut paper, Fold::Horizontal(7)).unwrap();
         assert_eq!(dot_count(&paper), 17);
-        fold_vertically(&mut paper, 5);
+        fold(&mut paper, Fold::Vertical(5)).unwrap();
         assert_eq!(dot_count(&paper), 16);
     }
+
+    #[test]
+    fn p2_test() {
+        assert_eq!(
+            render(&fold_all(TEST_INPUT).unwrap()),
+            "#####\n#...#\n#...#\n#...#\n#####\n.....\n.....\n"
+        );
+        assert_eq!(puzzle2(INPUT), "AHGCPGAU");
+    }
+
+    #[test]
+    fn recognize_test() {
+        let text = "HI, FOLKS";
+        let letters: Vec<_> = text
+            .chars()
+            .filter_map(|c| FONT.iter().find(|(letter, _)| *letter == c))
+            .collect();
+        let mut paper = Paper {
+            dots: HashSet::new(),
+            width: letters.len() * (GLYPH_WIDTH + 1),
+            height: GLYPH_HEIGHT,
+        };
+        for (i, (_, rows)) in letters.iter().enumerate() {
+            for (y, row) in rows.iter().enumerate() {
+                for (x, c) in row.chars().enumerate() {
+                    if c == '#' {
+                        paper.dots.insert((i * (GLYPH_WIDTH + 1) + x, y));
+                    }
+                }
+            }
+        }
+        assert_eq!(recognize(&paper), Ok("HIFOLKS".to_string()));
+
+        assert_eq!(
+            recognize(&fold_all(TEST_INPUT).unwrap()),
+            Err(OcrError::WrongHeight(7))
+        );
+
+        paper.dots.remove(&(6, 0));
+        assert_eq!(
+            recognize(&paper),
+            Err(OcrError::UnknownGlyph {
+                position: 1,
+                glyph: "..##\n..#.\n..#.\n..#.\n..#.\n.###".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn huge_coordinates() {
+        let input = "0,0
+2,4000000000
+3999999998,0
+
+fold along y=2000000000
+fold along x=1999999999
+fold along x=1";
+
+        let paper = fold_all(input).unwrap();
+        assert_eq!((paper.width, paper.height), (1999999997, 2000000000));
+        assert_eq!(dot_count(&paper), 1);
+        assert!(paper.dots.contains(&(1999999996, 0)));
+    }
+
+    #[test]
+    fn uncentered_folds() {
+        let (mut paper, _) = read_input("0,0\n3,0\n5,1\n\n");
+        assert_eq!(render(&paper), "#..#..\n.....#\n");
+
+        // the right part is the larger one and ends up sticking out to the left
+        fold(&mut paper, Fold::Vertical(1)).unwrap();
+        assert_eq!((paper.width, paper.height), (4, 2));
+        assert_eq!(render(&paper), "..##\n#...\n");
+
+        fold(&mut paper, Fold::Horizontal(0)).unwrap_err();
+        fold(&mut paper, Fold::Vertical(1)).unwrap();
+        assert_eq!(render(&paper), "##\n.#\n");
+    }
+
+    #[test]
+    fn fold_errors() {
+        let (mut paper, _) = read_input("0,0\n2,1\n4,2\n\n");
+        assert_eq!(
+            fold(&mut paper, Fold::Vertical(5)),
+            Err(FoldError::OutOfRange {
+                fold: Fold::Vertical(5),
+                size: 5
+            })
+        );
+        assert_eq!(
+            fold(&mut paper, Fold::Horizontal(1)),
+            Err(FoldError::DotOnFoldLine {
+                fold: Fold::Horizontal(1),
+                dot: (2, 1)
+            })
+        );
+        assert_eq!(
+            FoldError::DotOnFoldLine {
+                fold: Fold::Horizontal(1),
+                dot: (2, 1)
+            }
+            .to_string(),
+            "Horizontal(1) goes through the dot at (2, 1)"
+        );
+        assert_eq!(render(&paper), "#....\n..#..\n....#\n");
+    }
+
+    #[test]
+    fn frames_test() {
+        let frames = fold_frames(TEST_INPUT).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!((frames[0].width, frames[0].height), (11, 15));
+        assert_eq!(dot_count(&frames[0]), 18);
+        assert_eq!(dot_count(&frames[1]), 17);
+        assert_eq!(render(&frames[2]), render(&fold_all(TEST_INPUT).unwrap()));
+
+        assert_eq!(
+            to_pbm(&frames[2]),
+            "P1
+5 7
+1 1 1 1 1
+1 0 0 0 1
+1 0 0 0 1
+1 0 0 0 1
+1 1 1 1 1
+0 0 0 0 0
+0 0 0 0 0
+"
+        );
+    }
 }
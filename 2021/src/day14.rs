@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 
 const INPUT: &str = include_str!("../inputs/day14");
 
@@ -12,8 +13,8 @@ fn read_input(input: &str) -> (Vec<char>, HashMap<(char, char), char>) {
         .map(|l| {
             let (pair, insert) = l.split_once(" -> ").unwrap();
             (
-                (pair.chars().nth(0).unwrap(), pair.chars().nth(1).unwrap()),
-                insert.chars().nth(0).unwrap(),
+                (pair.chars().next().unwrap(), pair.chars().nth(1).unwrap()),
+                insert.chars().next().unwrap(),
             )
         })
         .collect();
@@ -21,6 +22,167 @@ fn read_input(input: &str) -> (Vec<char>, HashMap<(char, char), char>) {
     (template, rules)
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, PartialEq)]
+enum Issue {
+    MalformedRule,
+    DuplicateRule {
+        pair: (char, char),
+        first_line: usize,
+    },
+    ConflictingRule {
+        pair: (char, char),
+        first_line: usize,
+        first_insert: char,
+        insert: char,
+    },
+    MissingRule {
+        pair: (char, char),
+    },
+    UnproducibleElement {
+        element: char,
+    },
+}
+
+impl Issue {
+    fn severity(&self) -> Severity {
+        match self {
+            Issue::MalformedRule | Issue::ConflictingRule { .. } => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Finding {
+    line: usize,
+    issue: Issue,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.issue.severity() {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "line {}: {}: ", self.line, severity)?;
+
+        match self.issue {
+            Issue::MalformedRule => write!(f, "expected a rule like `AB -> C`"),
+            Issue::DuplicateRule {
+                pair: (l, r),
+                first_line,
+            } => write!(f, "rule for {}{} repeats line {}", l, r, first_line),
+            Issue::ConflictingRule {
+                pair: (l, r),
+                first_line,
+                first_insert,
+                insert,
+            } => write!(
+                f,
+                "rule for {}{} inserts {}, but line {} inserts {}",
+                l, r, insert, first_line, first_insert
+            ),
+            Issue::MissingRule { pair: (l, r) } => {
+                write!(f, "pair {}{} can occur but has no rule", l, r)
+            }
+            Issue::UnproducibleElement { element } => {
+                write!(f, "element {} can never be part of the polymer", element)
+            }
+        }
+    }
+}
+
+// checks the rule set without changing how `read_input` treats it, i.e. later rules still win.
+// line numbers are 1-based and count the template as line 1.
+fn validate(input: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    let mut lines = input.lines().enumerate().map(|(i, l)| (i + 1, l));
+    let template: Vec<char> = lines.next().map_or("", |(_, l)| l).chars().collect();
+
+    // pair -> (line, insert) of the rule that ends up in effect
+    let mut rules: HashMap<(char, char), (usize, char)> = HashMap::new();
+    // element -> first line that mentions it
+    let mut mentioned: BTreeMap<char, usize> = BTreeMap::new();
+
+    for (line, l) in lines.filter(|(_, l)| !l.is_empty()) {
+        let parsed = l.split_once(" -> ").and_then(|(pair, insert)| {
+            let pair: Vec<char> = pair.chars().collect();
+            let insert: Vec<char> = insert.chars().collect();
+            match (&pair[..], &insert[..]) {
+                (&[l, r], &[i]) => Some(((l, r), i)),
+                _ => None,
+            }
+        });
+        let Some((pair, insert)) = parsed else {
+            findings.push(Finding {
+                line,
+                issue: Issue::MalformedRule,
+            });
+            continue;
+        };
+
+        for element in [pair.0, pair.1, insert] {
+            mentioned.entry(element).or_insert(line);
+        }
+
+        if let Some((first_line, first_insert)) = rules.insert(pair, (line, insert)) {
+            let issue = if first_insert == insert {
+                Issue::DuplicateRule { pair, first_line }
+            } else {
+                Issue::ConflictingRule {
+                    pair,
+                    first_line,
+                    first_insert,
+                    insert,
+                }
+            };
+            findings.push(Finding { line, issue });
+        }
+    }
+
+    // walk every pair that can ever show up, remembering which line first produced it
+    let mut queue: Vec<((char, char), usize)> =
+        template.windows(2).map(|w| ((w[0], w[1]), 1)).collect();
+    let mut reachable = HashSet::new();
+    let mut produced: HashSet<char> = template.iter().copied().collect();
+
+    while let Some((pair, origin)) = queue.pop() {
+        if !reachable.insert(pair) {
+            continue;
+        }
+        match rules.get(&pair) {
+            Some(&(line, insert)) => {
+                produced.insert(insert);
+                queue.push(((pair.0, insert), line));
+                queue.push(((insert, pair.1), line));
+            }
+            None => findings.push(Finding {
+                line: origin,
+                issue: Issue::MissingRule { pair },
+            }),
+        }
+    }
+
+    for (&element, &line) in &mentioned {
+        if !produced.contains(&element) {
+            findings.push(Finding {
+                line,
+                issue: Issue::UnproducibleElement { element },
+            });
+        }
+    }
+
+    findings.sort_by_key(|finding| finding.line);
+    findings
+}
+
 fn step(template: &[char], rules: &HashMap<(char, char), char>) -> Vec<char> {
     let mut new_template = Vec::new();
     for (left, right) in template.iter().zip(template.iter().skip(1)) {
@@ -33,36 +195,285 @@ fn step(template: &[char], rules: &HashMap<(char, char), char>) -> Vec<char> {
     new_template
 }
 
-fn puzzle(input: &str, steps: usize) -> usize {
+// builds the actual polymer, so only use this for small step counts
+fn polymer_after(input: &str, steps: usize) -> String {
     let (mut template, rules) = read_input(input);
 
     for _ in 0..steps {
         template = step(&template, &rules);
     }
 
-    let char_counts = template.iter().fold(HashMap::new(), |mut counts, &c| {
-        if let Some(x) = counts.get_mut(&c) {
-            *x += 1;
-        } else {
-            counts.insert(c, 1);
-        }
+    template.into_iter().collect()
+}
+
+fn histogram(polymer: impl IntoIterator<Item = char>) -> BTreeMap<char, usize> {
+    polymer.into_iter().fold(BTreeMap::new(), |mut counts, c| {
+        *counts.entry(c).or_default() += 1;
         counts
-    });
+    })
+}
 
-    let (_, min) = char_counts.iter().min_by_key(|&(_, v)| v).unwrap();
-    let (_, max) = char_counts.iter().max_by_key(|&(_, v)| v).unwrap();
+fn puzzle(input: &str, steps: usize) -> usize {
+    let char_counts = histogram(polymer_after(input, steps).chars());
+
+    let min = char_counts.values().min().unwrap();
+    let max = char_counts.values().max().unwrap();
 
     max - min
 }
 
+struct StepStats {
+    step: usize,
+    length: usize,
+    histogram: BTreeMap<char, usize>,
+}
+
+impl fmt::Display for StepStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "step {}: length {}", self.step, self.length)?;
+        for (element, count) in &self.histogram {
+            write!(f, ", {}: {}", element, count)?;
+        }
+        Ok(())
+    }
+}
+
+fn step_pairs(
+    pairs: &HashMap<(char, char), usize>,
+    rules: &HashMap<(char, char), char>,
+) -> HashMap<(char, char), usize> {
+    let mut new_pairs = HashMap::new();
+    for (&(left, right), &count) in pairs {
+        if let Some(&insert) = rules.get(&(left, right)) {
+            *new_pairs.entry((left, insert)).or_default() += count;
+            *new_pairs.entry((insert, right)).or_default() += count;
+        } else {
+            *new_pairs.entry((left, right)).or_default() += count;
+        }
+    }
+    new_pairs
+}
+
+// element counts and polymer length for every step from 0 to `steps`.
+// works on pair counts, so it stays cheap for as long as the counts fit into a usize.
+fn trace(input: &str, steps: usize) -> Vec<StepStats> {
+    let (template, rules) = read_input(input);
+    let last = *template.last().unwrap();
+
+    let mut pairs = HashMap::new();
+    for window in template.windows(2) {
+        *pairs.entry((window[0], window[1])).or_default() += 1;
+    }
+
+    let mut stats = Vec::with_capacity(steps + 1);
+    for step in 0..=steps {
+        if step > 0 {
+            pairs = step_pairs(&pairs, &rules);
+        }
+
+        let mut histogram = BTreeMap::new();
+        for (&(left, _), &count) in &pairs {
+            *histogram.entry(left).or_default() += count;
+        }
+        *histogram.entry(last).or_default() += 1;
+
+        stats.push(StepStats {
+            step,
+            length: histogram.values().sum(),
+            histogram,
+        });
+    }
+    stats
+}
+
+type Matrix = Vec<Vec<u64>>;
+
+// how counts are kept: exactly, failing once they no longer fit, or modulo some number
+#[derive(Debug, Clone, Copy)]
+enum Counting {
+    Exact,
+    Modulo(u64),
+}
+
+#[derive(Debug, PartialEq)]
+enum CountError {
+    Overflow,
+    ZeroModulus,
+}
+
+impl fmt::Display for CountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CountError::Overflow => write!(f, "the counts don't fit into 64 bits"),
+            CountError::ZeroModulus => write!(f, "can't count modulo 0"),
+        }
+    }
+}
+
+impl Counting {
+    fn reduce(self, n: u128) -> Result<u64, CountError> {
+        match self {
+            Counting::Exact => u64::try_from(n).map_err(|_| CountError::Overflow),
+            Counting::Modulo(modulus) => Ok((n % modulus as u128) as u64),
+        }
+    }
+
+    fn add(self, a: u64, b: u64) -> Result<u64, CountError> {
+        self.reduce(a as u128 + b as u128)
+    }
+
+    // sums are only reduced once they are complete, or when the next term wouldn't fit anymore.
+    // a reduced sum is below 2^64 and a term at most (2^64 - 1)^2, so that always fits again.
+    fn accumulate(self, sum: u128, term: u128) -> Result<u128, CountError> {
+        match sum.checked_add(term) {
+            Some(sum) => Ok(sum),
+            None => Ok(self.reduce(sum)? as u128 + term),
+        }
+    }
+}
+
+fn mul_matrix(a: &Matrix, b: &Matrix, counting: Counting) -> Result<Matrix, CountError> {
+    let mut sums = vec![0u128; b[0].len()];
+    a.iter()
+        .map(|a_row| {
+            sums.fill(0);
+            for (&a_ik, b_row) in a_row.iter().zip(b) {
+                if a_ik == 0 {
+                    continue;
+                }
+                for (sum, &b_kj) in sums.iter_mut().zip(b_row) {
+                    *sum = counting.accumulate(*sum, a_ik as u128 * b_kj as u128)?;
+                }
+            }
+            sums.iter().map(|&sum| counting.reduce(sum)).collect()
+        })
+        .collect()
+}
+
+// matrix^exponent * vector, by repeated squaring. the powers are applied to the vector one at a
+// time, so only the squarings have to multiply whole matrices.
+fn apply_power(
+    matrix: &Matrix,
+    mut exponent: u64,
+    vector: Matrix,
+    counting: Counting,
+) -> Result<Matrix, CountError> {
+    let mut result = vector;
+    let mut base = matrix.clone();
+
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_matrix(&base, &result, counting)?;
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = mul_matrix(&base, &base, counting)?;
+        }
+    }
+    Ok(result)
+}
+
+// counts every element after the given number of steps.
+// pairs are tracked instead of the polymer itself: each pair either splits into two new pairs
+// or stays as it is, which is a linear map that can be exponentiated in O(log steps).
+// exact counts fail as soon as any intermediate count grows past 64 bits, so a result is
+// never silently wrapped.
+fn element_counts_after(
+    input: &str,
+    steps: u64,
+    counting: Counting,
+) -> Result<HashMap<char, u64>, CountError> {
+    if let Counting::Modulo(0) = counting {
+        return Err(CountError::ZeroModulus);
+    }
+
+    let (template, rules) = read_input(input);
+
+    let mut elements: Vec<char> = template
+        .iter()
+        .copied()
+        .chain(rules.iter().flat_map(|(&(l, r), &i)| [l, r, i]))
+        .collect();
+    elements.sort_unstable();
+    elements.dedup();
+
+    let n = elements.len();
+    let index = |c: char| elements.binary_search(&c).unwrap();
+    let pair = |l: char, r: char| index(l) * n + index(r);
+
+    // transitions[to][from] is how many `to` pairs a single `from` pair turns into in one step
+    let mut transitions = vec![vec![0; n * n]; n * n];
+    for &l in &elements {
+        for &r in &elements {
+            if let Some(&insert) = rules.get(&(l, r)) {
+                transitions[pair(l, insert)][pair(l, r)] += 1;
+                transitions[pair(insert, r)][pair(l, r)] += 1;
+            } else {
+                transitions[pair(l, r)][pair(l, r)] += 1;
+            }
+        }
+    }
+
+    let mut pair_counts = vec![vec![0]; n * n];
+    for window in template.windows(2) {
+        pair_counts[pair(window[0], window[1])][0] += 1;
+    }
+    let pair_counts = apply_power(&transitions, steps, pair_counts, counting)?;
+
+    // every element is the left half of exactly one pair, except the last one which never moves
+    let mut counts = HashMap::new();
+    for (i, &l) in elements.iter().enumerate() {
+        let count = pair_counts[i * n..(i + 1) * n]
+            .iter()
+            .try_fold(0, |sum, count| counting.add(sum, count[0]))?;
+        counts.insert(l, count);
+    }
+    if let Some(last) = template.last() {
+        let count = counts.get_mut(last).unwrap();
+        *count = counting.add(*count, 1)?;
+    }
+
+    Ok(counts)
+}
+
+fn puzzle_fast(input: &str, steps: u64) -> Result<u64, CountError> {
+    let counts = element_counts_after(input, steps, Counting::Exact)?;
+    // elements that only show up in the rules may never be inserted
+    let present = counts.values().filter(|&&count| count > 0);
+
+    let min = present.clone().min().unwrap();
+    let max = present.max().unwrap();
+
+    Ok(max - min)
+}
+
 pub fn day14() {
-    let p1 = puzzle(&INPUT, 10);
+    for finding in validate(INPUT) {
+        println!("d14 {}", finding);
+    }
+
+    let p1 = puzzle(INPUT, 10);
     println!("d14 p1: {}", p1);
+
+    let p2 = puzzle_fast(INPUT, 40).unwrap_or_else(|e| panic!("{}", e));
+    println!("d14 p2: {}", p2);
+
+    let after_40 = trace(INPUT, 40).pop().unwrap();
+    println!("d14 {}", after_40);
+
+    let counts = element_counts_after(INPUT, 1_000_000_000_000, Counting::Modulo(1_000_000_007))
+        .unwrap_or_else(|e| panic!("{}", e));
+    let counts: BTreeMap<char, u64> = counts.into_iter().collect();
+    println!(
+        "d14 element counts after 10^12 steps, mod 10^9 + 7: {:?}",
+        counts
+    );
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::rng::Lcg;
 
     const TEST_INPUT: &str = r"NNCB
 
@@ -85,7 +496,221 @@ CN -> C";
 
     #[test]
     fn p1_test() {
-        let res = puzzle(&TEST_INPUT, 10);
+        let res = puzzle(TEST_INPUT, 10);
         assert_eq!(res, 1588);
     }
+
+    #[test]
+    fn validate_test() {
+        assert_eq!(validate(TEST_INPUT), vec![]);
+        assert_eq!(validate(INPUT), vec![]);
+
+        let input = "NNC
+
+NN -> C
+NC -> B
+NN -> C
+CB -> X
+NC -> N
+CN -> C
+BN
+";
+        let findings = validate(input);
+        assert_eq!(
+            findings,
+            vec![
+                Finding {
+                    line: 4,
+                    issue: Issue::UnproducibleElement { element: 'B' }
+                },
+                Finding {
+                    line: 5,
+                    issue: Issue::DuplicateRule {
+                        pair: ('N', 'N'),
+                        first_line: 3
+                    }
+                },
+                Finding {
+                    line: 6,
+                    issue: Issue::UnproducibleElement { element: 'X' }
+                },
+                Finding {
+                    line: 7,
+                    issue: Issue::ConflictingRule {
+                        pair: ('N', 'C'),
+                        first_line: 4,
+                        first_insert: 'B',
+                        insert: 'N'
+                    }
+                },
+                Finding {
+                    line: 8,
+                    issue: Issue::MissingRule { pair: ('C', 'C') }
+                },
+                Finding {
+                    line: 9,
+                    issue: Issue::MalformedRule
+                },
+            ]
+        );
+        assert_eq!(
+            findings[3].to_string(),
+            "line 7: error: rule for NC inserts N, but line 4 inserts B"
+        );
+    }
+
+    #[test]
+    fn polymer_test() {
+        assert_eq!(polymer_after(TEST_INPUT, 0), "NNCB");
+        assert_eq!(polymer_after(TEST_INPUT, 1), "NCNBCHB");
+        assert_eq!(polymer_after(TEST_INPUT, 2), "NBCCNBBBCBHCB");
+        assert_eq!(polymer_after(TEST_INPUT, 3), "NBBBCNCCNBBNBNBBCHBHHBCHB");
+        assert_eq!(
+            polymer_after(TEST_INPUT, 4),
+            "NBBNBNBBCCNBCNCCNBBNBBNBBBNBBNBBCBHCBHHNHCBBCBHCB"
+        );
+    }
+
+    #[test]
+    fn trace_test() {
+        let trace = trace(TEST_INPUT, 10);
+        assert_eq!(trace.len(), 11);
+
+        for stats in &trace[..5] {
+            let polymer = polymer_after(TEST_INPUT, stats.step);
+            assert_eq!(stats.length, polymer.len());
+            assert_eq!(stats.histogram, histogram(polymer.chars()));
+        }
+
+        let lengths: Vec<usize> = trace.iter().map(|stats| stats.length).collect();
+        assert_eq!(lengths[..6], [4, 7, 13, 25, 49, 97]);
+        assert_eq!(lengths[10], 3073);
+
+        let after_10 = &trace[10];
+        assert_eq!(
+            after_10.to_string(),
+            "step 10: length 3073, B: 1749, C: 298, H: 161, N: 865"
+        );
+    }
+
+    #[test]
+    fn fast_matches_naive() {
+        assert_eq!(puzzle_fast(TEST_INPUT, 10), Ok(1588));
+        assert_eq!(puzzle_fast(TEST_INPUT, 40), Ok(2188189693529));
+
+        let counts = element_counts_after(TEST_INPUT, 10, Counting::Exact).unwrap();
+        assert_eq!(counts[&'B'], 1749);
+        assert_eq!(counts[&'C'], 298);
+        assert_eq!(counts[&'H'], 161);
+        assert_eq!(counts[&'N'], 865);
+    }
+
+    #[test]
+    fn billions_of_steps() {
+        const MODULUS: u64 = 1_000_000_007;
+        let steps = 1_000_000_000_000;
+
+        // the polymer length doubles minus one every step: (4 - 1) * 2^steps + 1
+        let mut expected_length = 3;
+        let (mut base, mut exponent) = (2u64, steps);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                expected_length = expected_length * base % MODULUS;
+            }
+            base = base * base % MODULUS;
+            exponent >>= 1;
+        }
+        expected_length = (expected_length + 1) % MODULUS;
+
+        let counts = element_counts_after(TEST_INPUT, steps, Counting::Modulo(MODULUS)).unwrap();
+        let length = counts
+            .values()
+            .fold(0, |sum, count| (sum + count) % MODULUS);
+        assert_eq!(length, expected_length);
+    }
+
+    #[test]
+    fn count_errors() {
+        // the polymer is (4 - 1) * 2^steps + 1 long, so the total stops fitting after 62 steps,
+        // but the single elements still do for one more step
+        let counts = element_counts_after(TEST_INPUT, 62, Counting::Exact).unwrap();
+        assert_eq!(counts.values().sum::<u64>(), 3 * (1 << 62) + 1);
+
+        let exact = element_counts_after(TEST_INPUT, 63, Counting::Exact).unwrap();
+        let modular =
+            element_counts_after(TEST_INPUT, 63, Counting::Modulo(1_000_000_007)).unwrap();
+        for (element, count) in &exact {
+            assert_eq!(count % 1_000_000_007, modular[element]);
+        }
+        assert_eq!(puzzle_fast(TEST_INPUT, 64), Err(CountError::Overflow));
+        assert_eq!(
+            element_counts_after(TEST_INPUT, 1000, Counting::Exact),
+            Err(CountError::Overflow)
+        );
+
+        assert_eq!(
+            element_counts_after(TEST_INPUT, 10, Counting::Modulo(0)),
+            Err(CountError::ZeroModulus)
+        );
+        let counts = element_counts_after(TEST_INPUT, 10, Counting::Modulo(1)).unwrap();
+        assert!(counts.values().all(|&count| count == 0));
+    }
+
+    #[test]
+    fn reduce_once_per_cell() {
+        // close to 2^64, so every product is close to 2^128 and the sums overflow right away
+        let modulus = u64::MAX - 58;
+        let counting = Counting::Modulo(modulus);
+        let mut rng = Lcg::new(14);
+        let mut random_matrix = || -> Matrix {
+            (0..5)
+                .map(|_| (0..5).map(|_| u64::MAX - rng.below(100)).collect())
+                .collect()
+        };
+        let (a, b) = (random_matrix(), random_matrix());
+
+        let mut expected = vec![vec![0; 5]; 5];
+        for (i, row) in expected.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                for k in 0..5 {
+                    let term = a[i][k] as u128 * b[k][j] as u128 % modulus as u128;
+                    *cell = ((*cell as u128 + term) % modulus as u128) as u64;
+                }
+            }
+        }
+        assert_eq!(mul_matrix(&a, &b, counting), Ok(expected));
+        assert_eq!(
+            mul_matrix(&a, &b, Counting::Exact),
+            Err(CountError::Overflow)
+        );
+    }
+
+    // every pair of 26 elements has a rule, which makes for 676 x 676 matrices.
+    // this takes about 15 seconds in a release build, so it only runs on request:
+    // cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn all_letters() {
+        let letters: Vec<char> = ('A'..='Z').collect();
+        let mut input: String = letters.iter().collect();
+        input += "\n\n";
+        for (i, &l) in letters.iter().enumerate() {
+            for (j, &r) in letters.iter().enumerate() {
+                input += &format!("{}{} -> {}\n", l, r, letters[(i * 7 + j * 3) % 26]);
+            }
+        }
+
+        let modulus = 1_000_000_007;
+        let exact = element_counts_after(&input, 20, Counting::Exact).unwrap();
+        let modular = element_counts_after(&input, 20, Counting::Modulo(modulus)).unwrap();
+        for (element, count) in &exact {
+            assert_eq!(count % modulus, modular[element]);
+        }
+
+        let start = std::time::Instant::now();
+        let counts =
+            element_counts_after(&input, 1_000_000_000_000, Counting::Modulo(modulus)).unwrap();
+        println!("10^12 steps took {:?}", start.elapsed());
+        assert_eq!(counts.len(), 26);
+    }
 }
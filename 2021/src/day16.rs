@@ -42,7 +42,7 @@ struct BitStream<'a> {
 }
 
 impl BitStream<'_> {
-    fn from(data: &mut [u8]) -> BitStream {
+    fn from(data: &mut [u8]) -> BitStream<'_> {
         BitStream {
             data,
             current_index: 0,
@@ -148,7 +148,7 @@ fn accumulate_versions(packet: &Packet) -> usize {
             PacketType::Operator { sub_packets, .. } => {
                 let mut sub_sum = 0;
                 for sub_packet in sub_packets {
-                    sub_sum += accumulate_versions(&sub_packet);
+                    sub_sum += accumulate_versions(sub_packet);
                 }
                 sub_sum
             }
@@ -163,7 +163,7 @@ fn packet_value(packet: &Packet) -> usize {
             operation,
             sub_packets,
         } => {
-            let mut iter = sub_packets.iter().map(|p| packet_value(p));
+            let mut iter = sub_packets.iter().map(packet_value);
             match operation {
                 Operation::Sum => iter.sum(),
                 Operation::Product => iter.product(),
@@ -1,5 +1,6 @@
 use std::env;
 
+mod animation;
 mod day01;
 mod day02;
 mod day03;
@@ -15,6 +16,9 @@ mod day12;
 mod day13;
 mod day14;
 mod day16;
+mod options;
+#[cfg(test)]
+mod rng;
 
 fn not_attempted() {}
 
@@ -40,10 +44,8 @@ fn main() {
         day16::day16,
     ];
 
-    let mut days_to_run: Vec<usize> = env::args()
-        .skip(1)
-        .map(|d| d.parse::<usize>().expect("could not parse cli argument") - 1)
-        .collect();
+    let (options, mut days_to_run) = options::parse(env::args().skip(1));
+    options::set(options);
 
     if days_to_run.is_empty() {
         days_to_run = (0..days.len()).collect();
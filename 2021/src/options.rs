@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+// output switches that apply to every day, given on the command line next to the days to run
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    // --animate: play the animations in the terminal
+    pub animate: bool,
+    // --frames DIR: also keep every animation frame as an image, implies --animate
+    pub frames: Option<PathBuf>,
+    // --render: print the bigger visualisations
+    pub render: bool,
+    // --export DIR: write data for plotting as csv or json files
+    pub export: Option<PathBuf>,
+}
+
+static OPTIONS: OnceLock<Options> = OnceLock::new();
+
+impl Options {
+    // where a day keeps its frames, one directory per day
+    pub fn frames_dir(&self, day: &str) -> Option<PathBuf> {
+        self.frames.as_ref().map(|dir| dir.join(day))
+    }
+
+    // where a day writes its exports, one directory per day
+    pub fn export_dir(&self, day: &str) -> Option<PathBuf> {
+        self.export.as_ref().map(|dir| dir.join(day))
+    }
+}
+
+// splits the arguments into options and the (0-based) days to run
+pub fn parse(args: impl IntoIterator<Item = String>) -> (Options, Vec<usize>) {
+    let mut options = Options::default();
+    let mut days = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut dir = || PathBuf::from(args.next().expect("expected a directory after the option"));
+        match arg.as_str() {
+            "--animate" => options.animate = true,
+            "--frames" => {
+                options.animate = true;
+                options.frames = Some(dir());
+            }
+            "--render" => options.render = true,
+            "--export" => options.export = Some(dir()),
+            day => days.push(day.parse::<usize>().expect("could not parse cli argument") - 1),
+        }
+    }
+
+    (options, days)
+}
+
+pub fn set(options: Options) {
+    OPTIONS.set(options).expect("options are already set");
+}
+
+// the defaults if nothing has been set, e.g. in tests
+pub fn get() -> &'static Options {
+    OPTIONS.get_or_init(Options::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(parse(args("")), (Options::default(), vec![]));
+        assert_eq!(parse(args("3 11")), (Options::default(), vec![2, 10]));
+
+        let (options, days) = parse(args("11 --frames out --export data 13 --render"));
+        assert_eq!(days, vec![10, 12]);
+        assert_eq!(
+            options,
+            Options {
+                animate: true,
+                frames: Some(PathBuf::from("out")),
+                render: true,
+                export: Some(PathBuf::from("data")),
+            }
+        );
+        assert_eq!(
+            options.frames_dir("day11"),
+            Some(PathBuf::from("out/day11"))
+        );
+        assert_eq!(
+            options.export_dir("day07"),
+            Some(PathBuf::from("data/day07"))
+        );
+        assert_eq!(Options::default().export_dir("day07"), None);
+    }
+}